@@ -0,0 +1,421 @@
+use crate::bitmap::BitMap;
+use crate::error::Error;
+use crate::level::Level;
+use crate::levelimage::{self, Palette};
+use crate::pack::Pack;
+use crate::tiled;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::video::WindowContext;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+//Environment variable with extra asset directories, separated like PATH
+pub const ASSET_PATH_VAR: &str = "RAYCAST_ASSETS";
+
+//Pack file that is mounted automatically if it is next to the executable or
+//in the current working directory
+pub const DEFAULT_PACK: &str = "data.pak";
+
+//Assets compiled into the binary, used when they are not found on disk or in
+//a pack so that the demo always runs
+const EMBEDDED: &[(&str, &[u8])] = &[
+    ("textures.png", include_bytes!("../assets/textures.png")),
+    ("sprite.png", include_bytes!("../assets/sprite.png")),
+    ("enemy.png", include_bytes!("../assets/enemy.png")),
+    ("weapon.png", include_bytes!("../assets/weapon.png")),
+    ("projectile.png", include_bytes!("../assets/projectile.png")),
+    ("decals.png", include_bytes!("../assets/decals.png")),
+    ("items.png", include_bytes!("../assets/items.png")),
+    ("doors.png", include_bytes!("../assets/doors.png")),
+    ("switches.png", include_bytes!("../assets/switches.png")),
+    ("font.png", include_bytes!("../assets/font.png")),
+];
+
+//How often the modification times of loaded files are checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+//A file on disk and the modification time it had when it was last loaded
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    fn new(path: PathBuf) -> WatchedFile {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        WatchedFile { path, modified }
+    }
+
+    //Returns true once each time the file changes
+    fn changed(&mut self) -> bool {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        //Ignore the file while it is missing, editors often delete and
+        //recreate files when saving
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        true
+    }
+}
+
+struct CachedBitMap {
+    bitmap: Rc<BitMap>,
+    //Bitmaps loaded from pack files or the binary are not watched
+    file: Option<WatchedFile>,
+}
+
+//Where an asset was found
+enum Location {
+    File(PathBuf),
+    //Index of the mounted pack that contains the asset
+    Pack(usize),
+    Embedded(&'static [u8]),
+}
+
+struct CachedTexture<'a> {
+    texture: Texture<'a>,
+    //Name of the bitmap the texture was created from
+    source: String,
+    color_mod: Color,
+}
+
+//Everything that was reloaded by `AssetManager::reload_changed`
+pub struct Reloaded {
+    pub bitmaps: Vec<String>,
+    pub level: Option<Level>,
+}
+
+//Finds assets by name in a list of directories and caches everything it
+//loads so that each file is only decoded once. Loaded files are watched and
+//reloaded in place when they change on disk. Mounted pack files are searched
+//after the directories so that loose files override packed ones.
+pub struct AssetManager<'a> {
+    search_paths: Vec<PathBuf>,
+    packs: Vec<Pack>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    bitmaps: HashMap<String, CachedBitMap>,
+    textures: HashMap<String, CachedTexture<'a>>,
+    level_file: Option<WatchedFile>,
+    last_poll: Instant,
+    //Colors of level images
+    pub palette: Palette,
+}
+
+//Directories to search in order: the ones given on the command line, the
+//ones in RAYCAST_ASSETS, `assets` next to the executable and finally
+//`assets` in the current working directory
+pub fn default_search_paths(extra: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = extra.to_vec();
+
+    if let Some(var) = env::var_os(ASSET_PATH_VAR) {
+        paths.extend(env::split_paths(&var).filter(|path| !path.as_os_str().is_empty()));
+    }
+
+    if let Some(exe_dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        paths.push(exe_dir.join("assets"));
+    }
+
+    paths.push(PathBuf::from("assets"));
+    paths
+}
+
+//Loads a level file, a Tiled map or a level image
+fn read_level(path: &Path, palette: &Palette) -> Result<Level, Error> {
+    if tiled::is_tiled_map(path) {
+        tiled::load(path)
+    } else if levelimage::is_level_image(path) {
+        levelimage::load(path, palette)
+    } else {
+        Level::load(path)
+    }
+}
+
+//Locations of DEFAULT_PACK that exist
+pub fn default_packs() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(exe_dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        paths.push(exe_dir.join(DEFAULT_PACK));
+    }
+    paths.push(PathBuf::from(DEFAULT_PACK));
+
+    paths.dedup_by(|a, b| fs::canonicalize(&*a).ok() == fs::canonicalize(&*b).ok());
+    paths.retain(|path| path.is_file());
+    paths
+}
+
+impl<'a> AssetManager<'a> {
+    pub fn new(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        search_paths: Vec<PathBuf>,
+    ) -> AssetManager<'a> {
+        AssetManager {
+            search_paths,
+            packs: Vec::new(),
+            texture_creator,
+            bitmaps: HashMap::new(),
+            textures: HashMap::new(),
+            level_file: None,
+            last_poll: Instant::now(),
+            palette: Palette::default(),
+        }
+    }
+
+    pub fn mount(&mut self, pack: Pack) {
+        self.packs.push(pack);
+    }
+
+    //The first file called `name` in the search path, or the first mounted
+    //pack that contains it, or the copy built into the binary
+    fn locate(&self, name: &str) -> Result<Location, Error> {
+        if let Some(path) = self
+            .search_paths
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+        {
+            return Ok(Location::File(path));
+        }
+
+        if let Some(i) = self.packs.iter().position(|pack| pack.contains(name)) {
+            return Ok(Location::Pack(i));
+        }
+
+        if let Some((_, data)) = EMBEDDED.iter().find(|(embedded, _)| *embedded == name) {
+            return Ok(Location::Embedded(data));
+        }
+
+        Err(Error::AssetNotFound {
+            path: PathBuf::from(name),
+            source: io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("not found in {}", self.search_path_list()),
+            ),
+        })
+    }
+
+    fn search_path_list(&self) -> String {
+        self.search_paths
+            .iter()
+            .chain(self.packs.iter().map(|pack| &pack.path))
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn bitmap(&mut self, name: &str) -> Result<Rc<BitMap>, Error> {
+        if let Some(cached) = self.bitmaps.get(name) {
+            return Ok(cached.bitmap.clone());
+        }
+
+        let (bitmap, file) = match self.locate(name)? {
+            Location::File(path) => (BitMap::from_png(&path)?, Some(WatchedFile::new(path))),
+            Location::Pack(i) => {
+                let pack = &self.packs[i];
+                let data = pack.read(name)?;
+                (BitMap::decode_png(&data[..], &pack.path.join(name))?, None)
+            }
+            Location::Embedded(data) => {
+                let path = Path::new("<embedded>").join(name);
+                (BitMap::decode_png(data, &path)?, None)
+            }
+        };
+
+        let bitmap = Rc::new(bitmap);
+        self.bitmaps.insert(
+            name.to_string(),
+            CachedBitMap {
+                bitmap: bitmap.clone(),
+                file,
+            },
+        );
+        Ok(bitmap)
+    }
+
+    fn create_texture(&self, bitmap: &BitMap, color_mod: Color) -> Result<Texture<'a>, Error> {
+        let mut texture = self
+            .texture_creator
+            .create_texture_static(
+                PixelFormatEnum::RGBA32,
+                bitmap.width as u32,
+                bitmap.height as u32,
+            )
+            .map_err(Error::sdl_init("texture"))?;
+        texture
+            .update(None, &bitmap.pixels, bitmap.width * 4)
+            .map_err(|e| Error::Sdl(e.to_string()))?;
+        texture.set_blend_mode(BlendMode::Blend);
+        texture.set_color_mod(color_mod.r, color_mod.g, color_mod.b);
+        Ok(texture)
+    }
+
+    //Creates an SDL texture called `key` from the bitmap `name`, tinted by
+    //`color_mod`, several textures can share the same bitmap
+    pub fn load_texture_tinted(
+        &mut self,
+        key: &str,
+        name: &str,
+        color_mod: Color,
+    ) -> Result<(), Error> {
+        if self.textures.contains_key(key) {
+            return Ok(());
+        }
+
+        let bitmap = self.bitmap(name)?;
+        let texture = self.create_texture(&bitmap, color_mod)?;
+        self.textures.insert(
+            key.to_string(),
+            CachedTexture {
+                texture,
+                source: name.to_string(),
+                color_mod,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn load_texture(&mut self, name: &str) -> Result<(), Error> {
+        self.load_texture_tinted(name, name, Color::WHITE)
+    }
+
+    pub fn texture(&self, key: &str) -> Result<&Texture<'a>, Error> {
+        self.textures
+            .get(key)
+            .map(|cached| &cached.texture)
+            .ok_or_else(|| Error::TextureNotLoaded(String::from(key)))
+    }
+
+    //Loads a level file and watches it for changes. `path` is used as is
+    //if it exists, otherwise it is looked up like any other asset.
+    pub fn load_level(&mut self, path: &Path) -> Result<Level, Error> {
+        let name = path.to_string_lossy();
+        let location = if path.is_file() {
+            Location::File(path.to_path_buf())
+        } else {
+            self.locate(&name)?
+        };
+
+        match location {
+            Location::File(path) => {
+                let level = read_level(&path, &self.palette)?;
+                self.level_file = Some(WatchedFile::new(path));
+                Ok(level)
+            }
+            Location::Pack(i) => {
+                let data = self.packs[i].read(&name)?;
+                self.level_file = None;
+                if levelimage::is_level_image(path) {
+                    let bitmap = BitMap::decode_png(data.as_slice(), path)?;
+                    return levelimage::from_bitmap(&bitmap, &self.palette, path);
+                }
+
+                let text = String::from_utf8(data).map_err(|_| {
+                    Error::InvalidLevel(format!("{name}: level file is not valid utf-8"))
+                })?;
+                if tiled::is_tiled_map(path) {
+                    tiled::parse(&text, path)
+                } else {
+                    Level::parse(&text)
+                }
+            }
+            Location::Embedded(_) => Err(Error::AssetNotFound {
+                path: path.to_path_buf(),
+                source: io::Error::new(io::ErrorKind::NotFound, "no such level file"),
+            }),
+        }
+    }
+
+    //Path of the level file on disk, if the level was loaded from one
+    pub fn level_path(&self) -> Option<&Path> {
+        self.level_file.as_ref().map(|file| file.path.as_path())
+    }
+
+    //Call after the demo itself wrote `path`, if it is the watched level file
+    //the write is not taken for an outside edit and reloaded
+    pub fn level_written(&mut self, path: &Path) {
+        if let Some(file) = &mut self.level_file {
+            if file.path == path {
+                file.changed();
+            }
+        }
+    }
+
+    //Reloads every bitmap, texture and level file that changed on disk since
+    //it was loaded. Files that fail to load keep their old contents so that a
+    //half saved file does not stop the demo.
+    pub fn reload_changed(&mut self) -> Reloaded {
+        let mut reloaded = Reloaded {
+            bitmaps: Vec::new(),
+            level: None,
+        };
+
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return reloaded;
+        }
+        self.last_poll = Instant::now();
+
+        for (name, cached) in self.bitmaps.iter_mut() {
+            let file = match &mut cached.file {
+                Some(file) => file,
+                None => continue,
+            };
+            if !file.changed() {
+                continue;
+            }
+
+            match BitMap::from_png(&file.path) {
+                Ok(bitmap) => {
+                    cached.bitmap = Rc::new(bitmap);
+                    reloaded.bitmaps.push(name.clone());
+                }
+                Err(err) => eprintln!("failed to reload {name}: {err}"),
+            }
+        }
+
+        for name in &reloaded.bitmaps {
+            let bitmap = self.bitmaps[name].bitmap.clone();
+            let keys: Vec<String> = self
+                .textures
+                .iter()
+                .filter(|(_, cached)| &cached.source == name)
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            for key in keys {
+                let color_mod = self.textures[&key].color_mod;
+                match self.create_texture(&bitmap, color_mod) {
+                    Ok(texture) => {
+                        if let Some(cached) = self.textures.get_mut(&key) {
+                            cached.texture = texture;
+                        }
+                    }
+                    Err(err) => eprintln!("failed to recreate texture {key}: {err}"),
+                }
+            }
+        }
+
+        if let Some(file) = &mut self.level_file {
+            if file.changed() {
+                match read_level(&file.path, &self.palette) {
+                    Ok(level) => reloaded.level = Some(level),
+                    Err(err) => eprintln!("failed to reload level: {err}"),
+                }
+            }
+        }
+
+        reloaded
+    }
+}
@@ -1,6 +1,10 @@
+use crate::error::Error;
 use std::cmp;
 use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
+//Pixels are stored as 8 bit RGBA regardless of the format of the png
 pub struct BitMap {
     pub pixels: Vec<u8>,
     pub width: usize,
@@ -9,37 +13,63 @@ pub struct BitMap {
 
 impl BitMap {
     //path: png image path
-    pub fn from_png(path: &str) -> Result<BitMap, String> {
-        let png_file = File::open(&path);
-
-        match png_file {
-            Ok(file) => {
-                let decoder = png::Decoder::new(file);
-                let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
-                //Bytes read in from the image, copoy this into the SDL texture
-                let mut png_buffer = vec![0u8; reader.output_buffer_size()];
-                // Read the next frame. An APNG might contain multiple frames.
-                let info = reader
-                    .next_frame(&mut png_buffer)
-                    .map_err(|e| e.to_string())?;
-                Ok(BitMap {
-                    pixels: png_buffer,
-                    width: info.width as usize,
-                    height: info.height as usize,
-                })
-            }
-            Err(msg) => {
-                eprintln!("{msg}");
-                eprintln!("failed to open: {path}");
-                Err(String::from("Failed to load bitmap from png file"))
+    pub fn from_png(path: &Path) -> Result<BitMap, Error> {
+        let file = File::open(path).map_err(|source| Error::AssetNotFound {
+            path: PathBuf::from(path),
+            source,
+        })?;
+        BitMap::decode_png(file, path)
+    }
+
+    //path is only used to report errors
+    pub fn decode_png<R: Read>(data: R, path: &Path) -> Result<BitMap, Error> {
+        let decode_failed = |source| Error::DecodeFailed {
+            path: PathBuf::from(path),
+            source,
+        };
+
+        let mut decoder = png::Decoder::new(data);
+        //Expand palettes and low bit depths, strip 16 bit channels to 8 bits
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(decode_failed)?;
+        //Bytes read in from the image, copoy this into the SDL texture
+        let mut png_buffer = vec![0u8; reader.output_buffer_size()];
+        // Read the next frame. An APNG might contain multiple frames.
+        let info = reader.next_frame(&mut png_buffer).map_err(decode_failed)?;
+        png_buffer.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => png_buffer,
+            png::ColorType::Rgb => png_buffer
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => png_buffer
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale | png::ColorType::Indexed => {
+                png_buffer.iter().flat_map(|&p| [p, p, p, 255]).collect()
             }
-        }
+        };
+
+        Ok(BitMap {
+            pixels,
+            width: info.width as usize,
+            height: info.height as usize,
+        })
+    }
+
+    //RGBA value of the pixel at (x, y)
+    pub fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let ind = (x + y * self.width) * 4;
+        &self.pixels[ind..ind + 4]
     }
 
     //x and y are in the range 0 to 1
     pub fn sample(&self, x: f64, y: f64, pixel_data: &mut [u8]) {
         //Out of range, return black
-        if x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
             pixel_data[0] = 0;
             pixel_data[1] = 0;
             pixel_data[2] = 0;
@@ -47,9 +77,9 @@ impl BitMap {
         }
 
         let ind = cmp::min(
-            (x * self.width as f64).floor() as usize * 3
-                + self.width * 3 * (y * self.height as f64).floor() as usize,
-            self.pixels.len() - 3,
+            (x * self.width as f64).floor() as usize * 4
+                + self.width * 4 * (y * self.height as f64).floor() as usize,
+            self.pixels.len() - 4,
         );
 
         pixel_data[0] = self.pixels[ind];
@@ -0,0 +1,144 @@
+use crate::error::Error;
+use crate::level::Level;
+use crate::levelimage::{self, Palette};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCREENSHOT_DIR: &str = "screenshots";
+const RECORDING_DIR: &str = "recordings";
+
+//Current UTC time formatted as YYYY-MM-DD_HH-MM-SS-mmm so that captures sort
+//by the time they were taken
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    //Convert days since the epoch to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}-{:03}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        now.subsec_millis()
+    )
+}
+
+//pixels: tightly packed RGB data
+pub fn save_png(path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<(), Error> {
+    let file = File::create(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let encode_failed = |source| Error::EncodeFailed {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut writer = encoder.write_header().map_err(encode_failed)?;
+    writer.write_image_data(pixels).map_err(encode_failed)
+}
+
+fn save_canvas(canvas: &Canvas<Window>, path: &Path) -> Result<(), Error> {
+    let (width, height) = canvas.output_size().map_err(Error::Sdl)?;
+    let pixels = canvas
+        .read_pixels(None, PixelFormatEnum::RGB24)
+        .map_err(Error::Sdl)?;
+    save_png(path, &pixels, width, height)
+}
+
+//Saves what has been drawn to the canvas so far, call this before presenting
+pub fn screenshot(canvas: &Canvas<Window>) -> Result<PathBuf, Error> {
+    fs::create_dir_all(SCREENSHOT_DIR).map_err(|source| Error::Io {
+        path: PathBuf::from(SCREENSHOT_DIR),
+        source,
+    })?;
+    let path = Path::new(SCREENSHOT_DIR).join(format!("screenshot-{}.png", timestamp()));
+    save_canvas(canvas, &path)?;
+    Ok(path)
+}
+
+//Saves the current level as a level image next to the screenshots
+pub fn level_image(level: &Level, palette: &Palette) -> Result<PathBuf, Error> {
+    fs::create_dir_all(SCREENSHOT_DIR).map_err(|source| Error::Io {
+        path: PathBuf::from(SCREENSHOT_DIR),
+        source,
+    })?;
+    let path = Path::new(SCREENSHOT_DIR).join(format!("level-{}.png", timestamp()));
+    levelimage::save(level, palette, &path)?;
+    Ok(path)
+}
+
+//Writes every `interval`th frame to numbered png files in its own directory
+pub struct Recorder {
+    pub interval: u32,
+    dir: Option<PathBuf>,
+    frame: u32,
+    saved: u32,
+}
+
+impl Recorder {
+    pub fn new(interval: u32) -> Recorder {
+        Recorder {
+            interval: interval.max(1),
+            dir: None,
+            frame: 0,
+            saved: 0,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    pub fn start(&mut self) -> Result<(), Error> {
+        let dir = Path::new(RECORDING_DIR).join(timestamp());
+        fs::create_dir_all(&dir).map_err(|source| Error::Io {
+            path: dir.clone(),
+            source,
+        })?;
+        self.dir = Some(dir);
+        self.frame = 0;
+        self.saved = 0;
+        Ok(())
+    }
+
+    //Returns the directory the frames were saved to and how many were saved
+    pub fn stop(&mut self) -> Option<(PathBuf, u32)> {
+        self.dir.take().map(|dir| (dir, self.saved))
+    }
+
+    //Call once per frame before presenting the canvas
+    pub fn capture_frame(&mut self, canvas: &Canvas<Window>) -> Result<(), Error> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        if self.frame.is_multiple_of(self.interval) {
+            save_canvas(canvas, &dir.join(format!("frame-{:05}.png", self.saved)))?;
+            self.saved += 1;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+}
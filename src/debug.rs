@@ -0,0 +1,183 @@
+use crate::font::Font;
+use crate::framebuffer::FrameBuffer;
+use sdl2::pixels::Color;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Face {
+    //The ray did not hit anything
+    #[default]
+    None,
+    //Hit a grid line with a constant x value
+    Vertical,
+    //Hit a grid line with a constant y value
+    Horizontal,
+}
+
+//What the wall ray of a single screen column hit
+#[derive(Clone, Copy, Default)]
+pub struct ColumnInfo {
+    pub depth: f64,
+    pub face: Face,
+    pub tile_type: u8,
+}
+
+pub struct DebugOverlay {
+    pub enabled: bool,
+    //While frozen the camera only moves when a step is requested
+    pub frozen: bool,
+    step_requested: bool,
+    //Column that is inspected in detail
+    pub cursor: usize,
+    //Depth that maps to the top of the graph
+    pub max_depth: f64,
+    pub graph_height: i32,
+}
+
+impl DebugOverlay {
+    pub fn new() -> DebugOverlay {
+        DebugOverlay {
+            enabled: false,
+            frozen: false,
+            step_requested: false,
+            cursor: 100,
+            max_depth: 8.0,
+            graph_height: 120,
+        }
+    }
+
+    pub fn toggle_freeze(&mut self) {
+        self.frozen = !self.frozen;
+        self.step_requested = false;
+    }
+
+    pub fn request_step(&mut self) {
+        if self.frozen {
+            self.step_requested = true;
+        }
+    }
+
+    pub fn move_cursor(&mut self, amount: isize, column_count: usize) {
+        self.cursor = (self.cursor as isize + amount).clamp(0, column_count as isize - 1) as usize;
+    }
+
+    //Time step to simulate this frame: the frame time when running, nothing
+    //while frozen and a fixed step when stepping through frozen frames
+    pub fn step_dt(&mut self, dt: f64) -> f64 {
+        if !self.frozen {
+            dt
+        } else if self.step_requested {
+            self.step_requested = false;
+            1.0 / 60.0
+        } else {
+            0.0
+        }
+    }
+
+    fn face_color(face: Face) -> Color {
+        match face {
+            Face::None => Color::RGBA(80, 80, 80, 200),
+            Face::Vertical => Color::RGBA(230, 80, 60, 220),
+            Face::Horizontal => Color::RGBA(60, 140, 230, 220),
+        }
+    }
+
+    fn tile_color(tile_type: u8) -> Color {
+        match tile_type {
+            0 => Color::RGBA(0, 0, 0, 200),
+            1 => Color::RGBA(160, 160, 160, 220),
+            2 => Color::RGBA(170, 90, 60, 220),
+            3 => Color::RGBA(70, 130, 200, 220),
+            4 => Color::RGBA(80, 170, 80, 220),
+            _ => Color::RGBA(200, 60, 200, 220),
+        }
+    }
+
+    //Draws the depth graph along the bottom of the screen with a strip of
+    //hit tile ids underneath it, `columns` are spread evenly across the width
+    pub fn draw(&self, font: &Font, framebuffer: &mut FrameBuffer, columns: &[ColumnInfo]) {
+        if !self.enabled || columns.is_empty() {
+            return;
+        }
+
+        let width = framebuffer.width() as i32;
+        let height = framebuffer.height() as i32;
+        let column_width = width / columns.len() as i32;
+        let strip_height = font.glyph_height as i32 + 4;
+        let strip_top = height - strip_height;
+        let graph_top = strip_top - self.graph_height;
+
+        for (i, column) in columns.iter().enumerate() {
+            let x = i as i32 * column_width;
+            let bar_height = if column.face == Face::None {
+                0
+            } else {
+                ((column.depth / self.max_depth).min(1.0) * self.graph_height as f64) as i32
+            };
+            let face_color = DebugOverlay::face_color(column.face);
+            let tile_color = DebugOverlay::tile_color(column.tile_type);
+
+            for px in x..x + column_width {
+                for py in graph_top..strip_top {
+                    let color = if py >= strip_top - bar_height {
+                        face_color
+                    } else {
+                        Color::RGBA(0, 0, 0, 120)
+                    };
+                    framebuffer.set_pixel(px, py, color);
+                }
+
+                for py in strip_top..height {
+                    framebuffer.set_pixel(px, py, tile_color);
+                }
+            }
+        }
+
+        //Label each run of the same tile if it is wide enough to fit the id
+        let mut run_start = 0;
+        for i in 1..=columns.len() {
+            if i < columns.len() && columns[i].tile_type == columns[run_start].tile_type {
+                continue;
+            }
+
+            let label = columns[run_start].tile_type.to_string();
+            let run_width = (i - run_start) as i32 * column_width;
+            let label_width = font.text_width(&label, 1) as i32;
+            if columns[run_start].tile_type != 0 && run_width >= label_width + 2 {
+                let x = run_start as i32 * column_width + (run_width - label_width) / 2;
+                font.draw_text(framebuffer, x, strip_top + 2, &label, 1, Color::BLACK);
+            }
+            run_start = i;
+        }
+
+        //Cursor column and its details
+        let cursor = self.cursor.min(columns.len() - 1);
+        let cursorx = cursor as i32 * column_width;
+        for py in 0..height {
+            framebuffer.set_pixel(cursorx, py, Color::YELLOW);
+        }
+
+        let column = &columns[cursor];
+        let face = match column.face {
+            Face::None => "NONE",
+            Face::Vertical => "VERTICAL",
+            Face::Horizontal => "HORIZONTAL",
+        };
+        let mut lines = vec![
+            format!("COLUMN: {cursor}"),
+            format!("DEPTH: {:.3}", column.depth),
+            format!("FACE: {face}"),
+            format!("TILE: {}", column.tile_type),
+        ];
+        if self.frozen {
+            lines.push(String::from("FROZEN"));
+        }
+
+        let line_height = font.glyph_height as i32 * 2;
+        let texty = graph_top - line_height * lines.len() as i32 - 4;
+        for (i, line) in lines.iter().enumerate() {
+            let y = texty + line_height * i as i32;
+            font.draw_text(framebuffer, 9, y + 1, line, 2, Color::BLACK);
+            font.draw_text(framebuffer, 8, y, line, 2, Color::YELLOW);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::error::Error;
+use crate::raycast::Raycast;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+//Oldest decals are replaced once a level has this many
+const MAX_DECALS: usize = 256;
+
+//Side of a wall cell, named after the direction it faces
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    North,
+    South,
+    East,
+    West,
+}
+
+//The frame of decals.png to draw, each is 16x16
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecalKind {
+    BulletHole,
+    Scorch,
+}
+
+impl DecalKind {
+    //Size on the wall in cells
+    fn size(self) -> f64 {
+        match self {
+            DecalKind::BulletHole => 0.12,
+            DecalKind::Scorch => 0.6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Decal {
+    pub cellx: usize,
+    pub celly: usize,
+    pub side: Side,
+    //Position of the center on the face, u goes along the wall like the
+    //texture does and v goes down from the top
+    pub u: f64,
+    pub v: f64,
+    pub kind: DecalKind,
+}
+
+//The wall face that `ray`, going towards `angle`, hit: the cell, its side and
+//the position along it. A push wall caught between cells counts as being in
+//the cell it is mostly in.
+pub fn face(ray: &Raycast, angle: f64) -> (isize, isize, Side, f64) {
+    let (x, y) = (ray.x.round() as isize, ray.y.round() as isize);
+    if ray.vertical {
+        if angle.cos() > 0.0 {
+            (x, ray.y.floor() as isize, Side::West, ray.u)
+        } else {
+            (x - 1, ray.y.floor() as isize, Side::East, ray.u)
+        }
+    } else if angle.sin() > 0.0 {
+        (ray.x.floor() as isize, y, Side::North, ray.u)
+    } else {
+        (ray.x.floor() as isize, y - 1, Side::South, ray.u)
+    }
+}
+
+//Decals on the walls of a level, kept in a ring buffer so the oldest go
+//first
+pub struct Decals {
+    decals: Vec<Decal>,
+    //Slot the next decal goes in once the buffer is full
+    next: usize,
+}
+
+impl Decals {
+    pub fn new() -> Decals {
+        Decals {
+            decals: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.decals.clear();
+        self.next = 0;
+    }
+
+    //Adds a decal where `ray`, going towards `angle`, hit a wall. `z` is the
+    //height of the hit above the floor.
+    pub fn add(&mut self, ray: &Raycast, z: f64, angle: f64, kind: DecalKind) {
+        let (cellx, celly, side, u) = face(ray, angle);
+        if cellx < 0 || celly < 0 {
+            return;
+        }
+
+        //Keep the whole decal between the floor and the ceiling
+        let size = kind.size();
+        let decal = Decal {
+            cellx: cellx as usize,
+            celly: celly as usize,
+            side,
+            u,
+            v: (1.0 - z).clamp(size / 2.0, 1.0 - size / 2.0),
+            kind,
+        };
+        if self.decals.len() < MAX_DECALS {
+            self.decals.push(decal);
+        } else {
+            self.decals[self.next] = decal;
+            self.next = (self.next + 1) % MAX_DECALS;
+        }
+    }
+
+    //Draws the decals covering one wall column over the wall already drawn in
+    //`wall_rect`. The column shows the face hit by `ray` going towards
+    //`angle`.
+    pub fn draw_column(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture: &Texture,
+        ray: &Raycast,
+        angle: f64,
+        wall_rect: Rect,
+    ) -> Result<(), Error> {
+        if self.decals.is_empty() {
+            return Ok(());
+        }
+
+        let (cellx, celly, side, u) = face(ray, angle);
+        for decal in &self.decals {
+            let size = decal.kind.size();
+            let offset = (u - decal.u) / size + 0.5;
+            if decal.cellx as isize != cellx
+                || decal.celly as isize != celly
+                || decal.side != side
+                || !(0.0..1.0).contains(&offset)
+            {
+                continue;
+            }
+
+            let height = wall_rect.height() as f64;
+            canvas
+                .copy(
+                    texture,
+                    Rect::new(decal.kind as i32 * 16 + (offset * 16.0) as i32, 0, 1, 16),
+                    Rect::new(
+                        wall_rect.x(),
+                        wall_rect.y() + ((decal.v - size / 2.0) * height) as i32,
+                        wall_rect.width(),
+                        ((size * height) as u32).max(1),
+                    ),
+                )
+                .map_err(Error::Sdl)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use crate::bitmap::BitMap;
+use crate::font::Font;
+use crate::framebuffer::FrameBuffer;
+use crate::hud::{Anchor, Hud, HudItem};
+use crate::level::{Layer, Level, LevelSprite, TILE_COUNT};
+use crate::minimap::{Minimap, MinimapMode};
+use sdl2::keyboard::Keycode;
+use std::f64::consts::PI;
+use std::path::PathBuf;
+
+//Oldest edits are forgotten once there are this many to undo
+const MAX_UNDO: usize = 100;
+
+//Sprite kinds that can be placed, picked with the number keys
+pub const SPRITE_KINDS: [&str; 8] = [
+    "decoration",
+    "enemy",
+    "health",
+    "ammo",
+    "rockets",
+    "armor",
+    "key_gold",
+    "key_silver",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Paint(Layer),
+    Sprites,
+    Start,
+}
+
+impl Tool {
+    //Cycle walls -> floor -> ceiling -> sprites -> start -> walls
+    pub fn next(self) -> Tool {
+        match self {
+            Tool::Paint(Layer::Walls) => Tool::Paint(Layer::Floor),
+            Tool::Paint(Layer::Floor) => Tool::Paint(Layer::Ceiling),
+            Tool::Paint(Layer::Ceiling) => Tool::Sprites,
+            Tool::Sprites => Tool::Start,
+            Tool::Start => Tool::Paint(Layer::Walls),
+        }
+    }
+}
+
+//Number of a number key
+pub fn digit(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num0 => Some(0),
+        Keycode::Num1 => Some(1),
+        Keycode::Num2 => Some(2),
+        Keycode::Num3 => Some(3),
+        Keycode::Num4 => Some(4),
+        Keycode::Num5 => Some(5),
+        Keycode::Num6 => Some(6),
+        Keycode::Num7 => Some(7),
+        Keycode::Num8 => Some(8),
+        Keycode::Num9 => Some(9),
+        _ => None,
+    }
+}
+
+//Highest tile id that can be painted with `texture` as the wall texture, one
+//per 16 pixel wide tile and no more than level files can hold
+pub fn tile_count(texture: &BitMap) -> u8 {
+    (texture.width / 16).min(TILE_COUNT as usize) as u8
+}
+
+//Edits the level on the minimap: while active the minimap is shown full
+//screen without fog and clicking on it paints the selected tool. Each mouse
+//stroke is one step of undo.
+pub struct Editor {
+    pub active: bool,
+    pub tool: Tool,
+    //Tile id painted with the left button, the right button paints 0
+    pub tile: u8,
+    //Highest tile id that can be picked, one per tile in the texture
+    pub tile_count: u8,
+    //Index into SPRITE_KINDS
+    pub sprite_kind: usize,
+    pub save_path: PathBuf,
+    pub status: HudItem,
+    undo: Vec<Level>,
+    redo: Vec<Level>,
+    //Level as it was when the current stroke started, until the stroke
+    //changes something
+    stroke_start: Option<Level>,
+    painting: bool,
+    last_cell: Option<(isize, isize)>,
+    //Cell under the mouse
+    pub cursor: Option<(isize, isize)>,
+    message: String,
+    //Minimap settings to restore when leaving the editor
+    saved_minimap: (MinimapMode, bool),
+}
+
+impl Editor {
+    pub fn new(save_path: PathBuf, tile_count: u8) -> Editor {
+        Editor {
+            active: false,
+            tool: Tool::Paint(Layer::Walls),
+            tile: 1,
+            tile_count,
+            sprite_kind: 0,
+            save_path,
+            status: HudItem::new(Anchor::TopLeft, 8, 8),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            stroke_start: None,
+            painting: false,
+            last_cell: None,
+            cursor: None,
+            message: String::new(),
+            saved_minimap: (MinimapMode::Corner, true),
+        }
+    }
+
+    pub fn toggle(&mut self, minimap: &mut Minimap) {
+        self.active = !self.active;
+        self.end_stroke();
+        if self.active {
+            self.saved_minimap = (minimap.mode, minimap.fog_of_war);
+            minimap.mode = MinimapMode::Fullscreen;
+            minimap.fog_of_war = false;
+        } else {
+            (minimap.mode, minimap.fog_of_war) = self.saved_minimap;
+        }
+    }
+
+    pub fn next_tool(&mut self) {
+        self.tool = self.tool.next();
+    }
+
+    //Number keys pick the tile id to paint or the sprite kind to place
+    pub fn select(&mut self, number: u8) {
+        match self.tool {
+            Tool::Paint(_) => {
+                if number <= self.tile_count {
+                    self.tile = number;
+                }
+            }
+            Tool::Sprites => {
+                if number >= 1 && number as usize <= SPRITE_KINDS.len() {
+                    self.sprite_kind = number as usize - 1;
+                }
+            }
+            Tool::Start => {}
+        }
+    }
+
+    //The layer shown on the minimap
+    pub fn layer(&self) -> Layer {
+        match self.tool {
+            Tool::Paint(layer) => layer,
+            _ => Layer::Walls,
+        }
+    }
+
+    pub fn begin_stroke(&mut self, level: &Level) {
+        self.stroke_start = Some(level.clone());
+        self.painting = true;
+        self.last_cell = None;
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.stroke_start = None;
+        self.painting = false;
+    }
+
+    //Remembers the level before the first change of a stroke
+    fn record_change(&mut self) {
+        if let Some(level) = self.stroke_start.take() {
+            self.undo.push(level);
+            if self.undo.len() > MAX_UNDO {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+    }
+
+    //Applies the current tool at world position (x, y), `erase` is set for
+    //the right mouse button. Each cell is only painted once per stroke.
+    pub fn paint(&mut self, level: &mut Level, x: f64, y: f64, erase: bool) {
+        let cell = (x.floor() as isize, y.floor() as isize);
+        if !self.painting || self.last_cell == Some(cell) {
+            return;
+        }
+        self.last_cell = Some(cell);
+        if cell.0 < 0
+            || cell.1 < 0
+            || cell.0 as usize >= level.width
+            || cell.1 as usize >= level.height
+        {
+            return;
+        }
+
+        let (cellx, celly) = (cell.0 as f64 + 0.5, cell.1 as f64 + 0.5);
+        let in_cell = |sprite: &LevelSprite| {
+            sprite.x.floor() == cell.0 as f64 && sprite.y.floor() == cell.1 as f64
+        };
+        match self.tool {
+            Tool::Paint(layer) => {
+                let tile = if erase { 0 } else { self.tile };
+                if level.layer(layer)[cell.0 as usize + cell.1 as usize * level.width] != tile {
+                    self.record_change();
+                    level.set_tile(layer, cell.0, cell.1, tile);
+                }
+            }
+            Tool::Sprites => {
+                if erase {
+                    if level.sprites.iter().any(in_cell) {
+                        self.record_change();
+                        level.sprites.retain(|sprite| !in_cell(sprite));
+                    }
+                } else if !level.sprites.iter().any(in_cell) {
+                    self.record_change();
+                    level.sprites.push(LevelSprite {
+                        kind: String::from(SPRITE_KINDS[self.sprite_kind]),
+                        x: cellx,
+                        y: celly,
+                    });
+                }
+            }
+            Tool::Start => {
+                self.record_change();
+                if erase {
+                    //Turn a quarter to the right
+                    level.start.angle = (level.start.angle + PI / 2.0) % (PI * 2.0);
+                } else {
+                    level.start.x = cellx;
+                    level.start.y = celly;
+                }
+            }
+        }
+    }
+
+    pub fn undo(&mut self, level: &mut Level) {
+        self.end_stroke();
+        match self.undo.pop() {
+            Some(previous) => {
+                self.redo.push(std::mem::replace(level, previous));
+                self.message.clear();
+            }
+            None => self.message = String::from("NOTHING TO UNDO"),
+        }
+    }
+
+    pub fn redo(&mut self, level: &mut Level) {
+        self.end_stroke();
+        match self.redo.pop() {
+            Some(next) => {
+                self.undo.push(std::mem::replace(level, next));
+                self.message.clear();
+            }
+            None => self.message = String::from("NOTHING TO REDO"),
+        }
+    }
+
+    pub fn save(&mut self, level: &Level) {
+        self.message = match level.save(&self.save_path) {
+            Ok(()) => format!("SAVED TO {}", self.save_path.display()),
+            Err(err) => {
+                eprintln!("failed to save level: {err}");
+                String::from("SAVE FAILED")
+            }
+        };
+    }
+
+    pub fn draw(&self, hud: &Hud, font: &Font, framebuffer: &mut FrameBuffer) {
+        if !self.active {
+            return;
+        }
+
+        let tool = match self.tool {
+            Tool::Paint(Layer::Walls) => format!("WALLS  TILE {}", self.tile),
+            Tool::Paint(Layer::Floor) => format!("FLOOR  TILE {}", self.tile),
+            Tool::Paint(Layer::Ceiling) => format!("CEILING  TILE {}", self.tile),
+            Tool::Sprites => format!("SPRITES  {}", SPRITE_KINDS[self.sprite_kind]),
+            Tool::Start => String::from("PLAYER START  RIGHT CLICK TURNS"),
+        };
+        let cursor = match self.cursor {
+            Some((x, y)) => format!("CELL {x} {y}"),
+            None => String::new(),
+        };
+        hud.draw_item(
+            font,
+            framebuffer,
+            &self.status,
+            &[
+                format!("EDITOR: {tool}"),
+                String::from("TAB TOOL  0-9 SELECT  CTRL+Z/Y UNDO/REDO  CTRL+S SAVE"),
+                cursor,
+                self.message.clone(),
+            ],
+        );
+    }
+}
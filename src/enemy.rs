@@ -0,0 +1,292 @@
+use crate::level::Level;
+use crate::pathfind::Pathfinder;
+use crate::projectile::{Projectile, ProjectileKind};
+use crate::raycast::{can_move, line_of_sight};
+use std::f64::consts::PI;
+
+//Movement speed in cells per second
+const SPEED: f64 = 1.2;
+//Enemies further away than this do not notice the player
+const SIGHT_RANGE: f64 = 12.0;
+const ATTACK_RANGE: f64 = 1.2;
+//Length of an attack, the damage is done at the end of it
+const ATTACK_TIME: f64 = 0.6;
+const ATTACK_DAMAGE: i32 = 8;
+//Players further away than ATTACK_RANGE but closer than this are shot at
+//with fireballs, at most once every RANGED_DELAY seconds
+const RANGED_RANGE: f64 = 8.0;
+const RANGED_DELAY: f64 = 2.5;
+const PAIN_TIME: f64 = 0.3;
+const HEALTH: i32 = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum State {
+    //Standing still until the player comes into view
+    Idle,
+    //Walking towards where the player was last seen
+    Chase,
+    Attack,
+    //Flinching after being hurt
+    Pain,
+    //Dying, stays on the last frame once the animation is done
+    Death,
+}
+
+//Frames of enemy.png used for a state
+struct Animation {
+    first: u32,
+    count: u32,
+    //Frames per second
+    rate: f64,
+    looping: bool,
+}
+
+fn animation(state: State) -> Animation {
+    match state {
+        State::Idle => Animation {
+            first: 0,
+            count: 2,
+            rate: 1.5,
+            looping: true,
+        },
+        State::Chase => Animation {
+            first: 2,
+            count: 4,
+            rate: 6.0,
+            looping: true,
+        },
+        State::Attack => Animation {
+            first: 6,
+            count: 2,
+            rate: 2.0 / ATTACK_TIME,
+            looping: false,
+        },
+        State::Pain => Animation {
+            first: 8,
+            count: 1,
+            rate: 1.0,
+            looping: false,
+        },
+        State::Death => Animation {
+            first: 9,
+            count: 4,
+            rate: 8.0,
+            looping: false,
+        },
+    }
+}
+
+pub struct Enemy {
+    pub x: f64,
+    pub y: f64,
+    pub state: State,
+    //Time spent in the current state
+    state_time: f64,
+    pub health: i32,
+    //Where the player was last seen
+    target: Option<(f64, f64)>,
+    //Whether the current attack is a ranged one
+    ranged: bool,
+    //Time until the next ranged attack
+    reload: f64,
+}
+
+//What an enemy does to the player at the end of an attack
+pub enum Attack {
+    //Damage done up close
+    Melee(i32),
+    //A projectile fired towards the player
+    Ranged(Projectile),
+}
+
+impl Enemy {
+    pub fn new(x: f64, y: f64) -> Enemy {
+        Enemy {
+            x,
+            y,
+            state: State::Idle,
+            state_time: 0.0,
+            health: HEALTH,
+            target: None,
+            ranged: false,
+            reload: RANGED_DELAY,
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+        self.state_time = 0.0;
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.state == State::Death
+    }
+
+    //Frame of enemy.png to draw
+    pub fn frame(&self) -> u32 {
+        let animation = animation(self.state);
+        let frame = (self.state_time * animation.rate) as u32;
+        if animation.looping {
+            animation.first + frame % animation.count
+        } else {
+            animation.first + frame.min(animation.count - 1)
+        }
+    }
+
+    pub fn hurt(&mut self, damage: i32) {
+        if self.is_dead() {
+            return;
+        }
+
+        self.health -= damage;
+        if self.health <= 0 {
+            self.set_state(State::Death);
+        } else {
+            self.set_state(State::Pain);
+        }
+    }
+
+    //Moves towards (x, y) sliding along walls, returns the distance left
+    fn walk_towards(&mut self, level: &Level, x: f64, y: f64, dt: f64) -> f64 {
+        let dist = ((x - self.x).powi(2) + (y - self.y).powi(2)).sqrt();
+        let step = (SPEED * dt).min(dist);
+        let angle = (y - self.y).atan2(x - self.x);
+
+        let stepx = angle.cos() * step;
+        if can_move(level, self.x, self.y, 0.0, stepx) {
+            self.x += stepx;
+        }
+        let stepy = angle.sin() * step;
+        if can_move(level, self.x, self.y, PI / 2.0, stepy) {
+            self.y += stepy;
+        }
+
+        ((x - self.x).powi(2) + (y - self.y).powi(2)).sqrt()
+    }
+
+    //Next point to walk to on the way to (x, y). Enemies that can see the
+    //player share a flow field towards them, the others follow a path to
+    //where the player was last seen. None if (x, y) can not be reached.
+    fn waypoint(
+        &self,
+        level: &Level,
+        pathfinder: &mut Pathfinder,
+        sees_player: bool,
+        x: f64,
+        y: f64,
+    ) -> Option<(f64, f64)> {
+        let cell = (self.x as usize, self.y as usize);
+        let goal = (x as usize, y as usize);
+        if cell == goal {
+            return Some((x, y));
+        }
+
+        let next = if sees_player {
+            pathfinder.flow_field(level, goal).next(level, cell)
+        } else {
+            pathfinder
+                .path(level, cell, goal)
+                .and_then(|path| path.get(1).copied())
+        }?;
+        Some((next.0 as f64 + 0.5, next.1 as f64 + 0.5))
+    }
+
+    //Runs the state machine for `dt` seconds, returns the attack made on the
+    //player at (playerx, playery) if one is finished
+    pub fn update(
+        &mut self,
+        level: &Level,
+        pathfinder: &mut Pathfinder,
+        dt: f64,
+        playerx: f64,
+        playery: f64,
+    ) -> Option<Attack> {
+        self.state_time += dt;
+        self.reload = (self.reload - dt).max(0.0);
+        if self.is_dead() {
+            return None;
+        }
+
+        let dist = ((playerx - self.x).powi(2) + (playery - self.y).powi(2)).sqrt();
+        let sees_player =
+            dist < SIGHT_RANGE && line_of_sight(level, self.x, self.y, playerx, playery);
+        if sees_player {
+            self.target = Some((playerx, playery));
+        }
+
+        match self.state {
+            State::Idle => {
+                if sees_player {
+                    self.set_state(State::Chase);
+                }
+            }
+            State::Chase => {
+                if sees_player && dist < ATTACK_RANGE {
+                    self.ranged = false;
+                    self.set_state(State::Attack);
+                } else if sees_player && dist < RANGED_RANGE && self.reload == 0.0 {
+                    self.ranged = true;
+                    self.set_state(State::Attack);
+                } else if let Some((x, y)) = self.target {
+                    //Give up once the last place the player was seen is
+                    //reached without finding them, or when there is no way
+                    //to get there
+                    let arrived = match self.waypoint(level, pathfinder, sees_player, x, y) {
+                        Some(waypoint) => {
+                            self.walk_towards(level, waypoint.0, waypoint.1, dt) < 0.1
+                                && waypoint == (x, y)
+                        }
+                        None => true,
+                    };
+                    if arrived && !sees_player {
+                        self.target = None;
+                        self.set_state(State::Idle);
+                    }
+                } else {
+                    self.set_state(State::Idle);
+                }
+            }
+            State::Attack => {
+                if self.state_time >= ATTACK_TIME {
+                    self.set_state(State::Chase);
+                    if self.ranged {
+                        //Fire at where the player was last seen even if they
+                        //got out of sight during the attack
+                        self.reload = RANGED_DELAY;
+                        let (x, y) = self.target.unwrap_or((playerx, playery));
+                        return Some(Attack::Ranged(Projectile::new(
+                            ProjectileKind::Fireball,
+                            self.x,
+                            self.y,
+                            (y - self.y).atan2(x - self.x),
+                            false,
+                        )));
+                    }
+                    if sees_player && dist < ATTACK_RANGE * 1.5 {
+                        return Some(Attack::Melee(ATTACK_DAMAGE));
+                    }
+                }
+            }
+            State::Pain => {
+                //Being hurt gives the player's position away
+                if self.state_time >= PAIN_TIME {
+                    self.target = Some((playerx, playery));
+                    self.set_state(State::Chase);
+                }
+            }
+            State::Death => {}
+        }
+
+        None
+    }
+}
+
+//An enemy for every sprite of kind "enemy" in the level
+pub fn spawn(level: &Level) -> Vec<Enemy> {
+    level
+        .sprites
+        .iter()
+        .filter(|sprite| sprite.kind == "enemy")
+        .map(|sprite| Enemy::new(sprite.x, sprite.y))
+        .collect()
+}
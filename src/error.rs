@@ -0,0 +1,98 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    //An asset file could not be opened
+    AssetNotFound {
+        path: PathBuf,
+        source: io::Error,
+    },
+    //An asset file was opened but its contents could not be decoded
+    DecodeFailed {
+        path: PathBuf,
+        source: png::DecodingError,
+    },
+    //SDL or one of its subsystems, windows or textures could not be created
+    SdlInit {
+        what: &'static str,
+        message: String,
+    },
+    //Unknown or malformed command line argument
+    InvalidArgument(String),
+    //An SDL call failed while running
+    Sdl(String),
+    //Level data is inconsistent, for example the layers have the wrong size
+    InvalidLevel(String),
+    //A file could not be read or written
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    //A pack file is corrupt or not a pack file
+    InvalidPack {
+        path: PathBuf,
+        message: String,
+    },
+    //An image could not be encoded as png
+    EncodeFailed {
+        path: PathBuf,
+        source: png::EncodingError,
+    },
+    //A texture was asked for before it was loaded
+    TextureNotLoaded(String),
+}
+
+impl Error {
+    //Wraps the error of an SDL call made while setting up
+    pub fn sdl_init<E: ToString>(what: &'static str) -> impl FnOnce(E) -> Error {
+        move |err| Error::SdlInit {
+            what,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AssetNotFound { path, .. } => {
+                write!(f, "failed to open asset: {}", path.display())
+            }
+            Error::DecodeFailed { path, .. } => write!(f, "failed to decode: {}", path.display()),
+            Error::SdlInit { what, message } => {
+                write!(f, "failed to initialize {what}: {message}")
+            }
+            Error::InvalidArgument(message) => write!(f, "invalid argument: {message}"),
+            Error::Sdl(message) => write!(f, "SDL error: {message}"),
+            Error::InvalidLevel(message) => write!(f, "invalid level: {message}"),
+            Error::Io { path, .. } => write!(f, "failed to access: {}", path.display()),
+            Error::InvalidPack { path, message } => {
+                write!(f, "invalid pack file {}: {message}", path.display())
+            }
+            Error::EncodeFailed { path, .. } => write!(f, "failed to encode: {}", path.display()),
+            Error::TextureNotLoaded(key) => {
+                write!(f, "texture {key} was used before it was loaded")
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::AssetNotFound { source, .. } => Some(source),
+            Error::DecodeFailed { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::EncodeFailed { source, .. } => Some(source),
+            Error::SdlInit { .. }
+            | Error::InvalidArgument(_)
+            | Error::Sdl(_)
+            | Error::InvalidLevel(_)
+            | Error::InvalidPack { .. }
+            | Error::TextureNotLoaded(_) => None,
+        }
+    }
+}
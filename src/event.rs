@@ -0,0 +1,189 @@
+use crate::level::{Layer, Level, LevelSprite, PlayerStart};
+
+//What an event does when it fires
+#[derive(Clone)]
+pub enum Action {
+    //Opens the door at (x, y), even a locked one
+    OpenDoor { x: usize, y: usize },
+    //Changes the wall tile at (x, y), 0 clears the cell
+    SetTile { x: usize, y: usize, tile: u8 },
+    Spawn(LevelSprite),
+    Teleport(PlayerStart),
+    EndLevel,
+}
+
+impl Action {
+    //Reads an action from the words after the event name in a level file:
+    //`open_door <x> <y>`, `set_tile <x> <y> <tile>`, `spawn <kind> <x> <y>`,
+    //`teleport <x> <y> <angle in degrees>` or `end_level`
+    pub fn parse(words: &[&str]) -> Result<Action, String> {
+        fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+            value
+                .parse::<T>()
+                .map_err(|_| format!("invalid number: {value}"))
+        }
+
+        match words {
+            ["open_door", x, y] => Ok(Action::OpenDoor {
+                x: number(x)?,
+                y: number(y)?,
+            }),
+            ["set_tile", x, y, tile] => Ok(Action::SetTile {
+                x: number(x)?,
+                y: number(y)?,
+                tile: number(tile)?,
+            }),
+            ["spawn", kind, x, y] => Ok(Action::Spawn(LevelSprite {
+                kind: kind.to_string(),
+                x: number(x)?,
+                y: number(y)?,
+            })),
+            ["teleport", x, y, angle] => Ok(Action::Teleport(PlayerStart {
+                x: number(x)?,
+                y: number(y)?,
+                angle: number::<f64>(angle)?.to_radians(),
+            })),
+            ["end_level"] => Ok(Action::EndLevel),
+            _ => Err(format!("unknown action: {}", words.join(" "))),
+        }
+    }
+
+    //The action in the format read by `Action::parse`
+    pub fn to_text(&self) -> String {
+        match self {
+            Action::OpenDoor { x, y } => format!("open_door {x} {y}"),
+            Action::SetTile { x, y, tile } => format!("set_tile {x} {y} {tile}"),
+            Action::Spawn(sprite) => format!("spawn {} {} {}", sprite.kind, sprite.x, sprite.y),
+            Action::Teleport(start) => format!(
+                "teleport {} {} {}",
+                start.x,
+                start.y,
+                start.angle.to_degrees()
+            ),
+            Action::EndLevel => String::from("end_level"),
+        }
+    }
+}
+
+//One action of the event called `event`, an event with several actions has a
+//handler for each and they run in order
+#[derive(Clone)]
+pub struct Handler {
+    pub event: String,
+    pub action: Action,
+}
+
+//An area of the level that fires `event` when the player walks into it,
+//`once` triggers only fire the first time
+#[derive(Clone)]
+pub struct Trigger {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub event: String,
+    pub once: bool,
+}
+
+impl Trigger {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+//A wall cell that fires `event` every time it is used, flipping between off
+//and on
+#[derive(Clone)]
+pub struct Switch {
+    pub x: usize,
+    pub y: usize,
+    pub event: String,
+    pub on: bool,
+}
+
+//Runs the events of a level. Events that fire are queued and their actions
+//run together once per frame by `dispatch`.
+pub struct Events {
+    queue: Vec<String>,
+    //Indices of the triggers the player was inside last frame, a trigger
+    //fires again only after the player has left it
+    inside: Vec<usize>,
+    //Indices of the `once` triggers that have fired
+    fired: Vec<usize>,
+}
+
+impl Events {
+    pub fn new() -> Events {
+        Events {
+            queue: Vec::new(),
+            inside: Vec::new(),
+            fired: Vec::new(),
+        }
+    }
+
+    //Forgets queued events and what the triggers did, for when the level
+    //starts over
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.inside.clear();
+        self.fired.clear();
+    }
+
+    pub fn fire(&mut self, event: &str) {
+        self.queue.push(String::from(event));
+    }
+
+    //Fires the triggers the player at (x, y) has just walked into
+    pub fn update_triggers(&mut self, level: &Level, x: f64, y: f64) {
+        let inside: Vec<usize> = level
+            .triggers
+            .iter()
+            .enumerate()
+            .filter(|(_, trigger)| trigger.contains(x, y))
+            .map(|(index, _)| index)
+            .collect();
+
+        for &index in &inside {
+            let trigger = &level.triggers[index];
+            if self.inside.contains(&index) || self.fired.contains(&index) {
+                continue;
+            }
+            if trigger.once {
+                self.fired.push(index);
+            }
+            self.queue.push(trigger.event.clone());
+        }
+        self.inside = inside;
+    }
+
+    //Runs the actions of every queued event. Doors and tiles are changed in
+    //`level` here, the actions that need the rest of the game are returned
+    //for the game loop to carry out.
+    pub fn dispatch(&mut self, level: &mut Level) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for event in std::mem::take(&mut self.queue) {
+            let handlers: Vec<Action> = level
+                .handlers
+                .iter()
+                .filter(|handler| handler.event == event)
+                .map(|handler| handler.action.clone())
+                .collect();
+
+            for action in handlers {
+                match action {
+                    Action::OpenDoor { x, y } => {
+                        let (x, y) = (x as isize, y as isize);
+                        if level.door_at(x, y).is_some_and(|door| !door.open) {
+                            level.toggle_door(x, y);
+                        }
+                    }
+                    Action::SetTile { x, y, tile } => {
+                        level.set_tile(Layer::Walls, x as isize, y as isize, tile);
+                    }
+                    action => actions.push(action),
+                }
+            }
+        }
+        actions
+    }
+}
@@ -0,0 +1,91 @@
+use crate::bitmap::BitMap;
+use crate::framebuffer::FrameBuffer;
+use sdl2::pixels::Color;
+use std::rc::Rc;
+
+//Fixed width bitmap font, glyphs are stored left to right, top to bottom in
+//a grid starting at the space character. Any pixel in the glyph sheet that is
+//not black is drawn.
+pub struct Font {
+    glyphs: Rc<BitMap>,
+    pub glyph_width: usize,
+    pub glyph_height: usize,
+    first_char: u8,
+    char_count: usize,
+}
+
+impl Font {
+    //glyphs: image with glyphs of glyph_width x glyph_height pixels
+    pub fn new(glyphs: Rc<BitMap>, glyph_width: usize, glyph_height: usize) -> Font {
+        let char_count = (glyphs.width / glyph_width) * (glyphs.height / glyph_height);
+        Font {
+            glyphs,
+            glyph_width,
+            glyph_height,
+            first_char: b' ',
+            char_count,
+        }
+    }
+
+    fn glyph_index(&self, ch: char) -> Option<usize> {
+        //The glyph sheet only has upper case letters
+        let ch = ch.to_ascii_uppercase();
+        if !ch.is_ascii() || (ch as u8) < self.first_char {
+            return None;
+        }
+
+        let ind = (ch as u8 - self.first_char) as usize;
+        if ind < self.char_count {
+            Some(ind)
+        } else {
+            None
+        }
+    }
+
+    //Width in pixels of the text when drawn at `scale`
+    pub fn text_width(&self, text: &str, scale: usize) -> usize {
+        text.chars().count() * self.glyph_width * scale
+    }
+
+    //Draws text with its top left corner at (x, y), anything outside of the
+    //framebuffer is clipped
+    pub fn draw_text(
+        &self,
+        framebuffer: &mut FrameBuffer,
+        x: i32,
+        y: i32,
+        text: &str,
+        scale: usize,
+        color: Color,
+    ) {
+        let columns = self.glyphs.width / self.glyph_width;
+
+        for (i, ch) in text.chars().enumerate() {
+            let glyph = match self.glyph_index(ch).or_else(|| self.glyph_index('?')) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let glyphx = (glyph % columns) * self.glyph_width;
+            let glyphy = (glyph / columns) * self.glyph_height;
+            let charx = x + (i * self.glyph_width * scale) as i32;
+
+            for gy in 0..self.glyph_height {
+                for gx in 0..self.glyph_width {
+                    if self.glyphs.pixel(glyphx + gx, glyphy + gy)[..3] == [0, 0, 0] {
+                        continue;
+                    }
+
+                    for sy in 0..scale {
+                        for sx in 0..scale {
+                            framebuffer.set_pixel(
+                                charx + (gx * scale + sx) as i32,
+                                y + (gy * scale + sy) as i32,
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
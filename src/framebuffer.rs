@@ -0,0 +1,40 @@
+use sdl2::pixels::Color;
+
+//Pixels of a locked BGRA8888 streaming texture
+pub struct FrameBuffer<'a> {
+    pub pixels: &'a mut [u8],
+    pub pitch: usize,
+}
+
+impl<'a> FrameBuffer<'a> {
+    pub fn new(pixels: &'a mut [u8], pitch: usize) -> FrameBuffer<'a> {
+        FrameBuffer { pixels, pitch }
+    }
+
+    pub fn width(&self) -> usize {
+        self.pitch / 4
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len() / self.pitch
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.a, color.r, color.g, color.b]);
+        }
+    }
+
+    //Pixels outside of the buffer are ignored
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x as usize >= self.width() || y as usize >= self.height() {
+            return;
+        }
+
+        let ind = y as usize * self.pitch + x as usize * 4;
+        self.pixels[ind] = color.a;
+        self.pixels[ind + 1] = color.r;
+        self.pixels[ind + 2] = color.g;
+        self.pixels[ind + 3] = color.b;
+    }
+}
@@ -0,0 +1,431 @@
+use crate::error::Error;
+use crate::level::{Level, LevelSprite, PlayerStart, MAX_SIZE};
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+const FLOOR_TILE: u8 = 2;
+const CEILING_TILE: u8 = 1;
+
+//Generated levels get one sprite for about this many empty cells
+const CELLS_PER_SPRITE: usize = 20;
+
+//Small deterministic random number generator (SplitMix64) so that a seed
+//always produces the same level, whatever platform it runs on
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    //Random number in min..max, max must be larger than min
+    pub fn range(&mut self, min: usize, max: usize) -> usize {
+        min + (self.next_u64() % (max - min) as u64) as usize
+    }
+
+    //True with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Algorithm {
+    //Recursive backtracker maze with corridors one cell wide
+    Maze,
+    //Rectangular rooms joined by corridors
+    Dungeon,
+    //Cellular automata caves
+    Cave,
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Algorithm, Error> {
+        match name {
+            "maze" => Ok(Algorithm::Maze),
+            "dungeon" => Ok(Algorithm::Dungeon),
+            "cave" => Ok(Algorithm::Cave),
+            _ => Err(Error::InvalidArgument(format!(
+                "unknown generator: {name}, expected maze, dungeon or cave"
+            ))),
+        }
+    }
+}
+
+//Wall layer being generated, every cell starts out as a wall
+struct Grid {
+    width: usize,
+    height: usize,
+    walls: Vec<u8>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize, tile: u8) -> Grid {
+        Grid {
+            width,
+            height,
+            walls: vec![tile; width * height],
+        }
+    }
+
+    fn is_wall(&self, x: usize, y: usize) -> bool {
+        self.walls[x + y * self.width] != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize, tile: u8) {
+        self.walls[x + y * self.width] = tile;
+    }
+
+    //Empty cells in row order
+    fn empty_cells(&self) -> Vec<(usize, usize)> {
+        (0..self.width * self.height)
+            .filter(|&i| self.walls[i] == 0)
+            .map(|i| (i % self.width, i / self.width))
+            .collect()
+    }
+}
+
+//Generates a `width` x `height` level, the same seed always gives the same
+//level. The outer border is always solid.
+pub fn generate(
+    algorithm: Algorithm,
+    width: usize,
+    height: usize,
+    seed: u64,
+) -> Result<Level, Error> {
+    if width < 5 || height < 5 {
+        return Err(Error::InvalidArgument(format!(
+            "generated levels must be at least 5x5, not {width}x{height}"
+        )));
+    }
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(Error::InvalidArgument(format!(
+            "generated levels must be at most {MAX_SIZE}x{MAX_SIZE}, not {width}x{height}"
+        )));
+    }
+
+    let mut rng = Rng::new(seed);
+    let grid = match algorithm {
+        Algorithm::Maze => maze(width, height, &mut rng),
+        Algorithm::Dungeon => dungeon(width, height, &mut rng),
+        Algorithm::Cave => cave(width, height, &mut rng),
+    };
+
+    let floor = grid
+        .walls
+        .iter()
+        .map(|&tile| if tile == 0 { FLOOR_TILE } else { 0 })
+        .collect();
+    let ceiling = grid
+        .walls
+        .iter()
+        .map(|&tile| if tile == 0 { CEILING_TILE } else { 0 })
+        .collect();
+    let mut cells = grid.empty_cells();
+    let mut level = Level::new(width, height, grid.walls, floor, ceiling)?;
+
+    //Start in the first empty cell facing an open neighbour, the rest of the
+    //empty cells are shuffled to place sprites in
+    let (startx, starty) = cells.remove(0);
+    let angle = [
+        (1, 0, 0.0),
+        (0, 1, PI * 0.5),
+        (-1, 0, PI),
+        (0, -1, PI * 1.5),
+    ]
+    .iter()
+    .find(|(dx, dy, _)| level.get_tile(startx as isize + dx, starty as isize + dy) == 0)
+    .map(|&(_, _, angle)| angle)
+    .unwrap_or(0.0);
+    level.start = PlayerStart {
+        x: startx as f64 + 0.5,
+        y: starty as f64 + 0.5,
+        angle,
+    };
+
+    for i in (1..cells.len()).rev() {
+        cells.swap(i, rng.range(0, i + 1));
+    }
+    let count = cells.len() / CELLS_PER_SPRITE;
+    for &(x, y) in cells.iter().take(count) {
+        let kind = match rng.range(0, 10) {
+            0..=3 => "enemy",
+            4 | 5 => "health",
+            6 | 7 => "ammo",
+            _ => "decoration",
+        };
+        level.sprites.push(LevelSprite {
+            kind: String::from(kind),
+            x: x as f64 + 0.5,
+            y: y as f64 + 0.5,
+        });
+    }
+
+    Ok(level)
+}
+
+//Carves passages between the cells at odd coordinates, always moving to a
+//random unvisited neighbour and backing up when there is none
+fn maze(width: usize, height: usize, rng: &mut Rng) -> Grid {
+    let mut grid = Grid::new(width, height, 1);
+    //Even sizes leave an extra wall along the right or bottom edge
+    let cells_x = (width - 1) / 2;
+    let cells_y = (height - 1) / 2;
+    let mut visited = vec![false; cells_x * cells_y];
+
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    grid.set(1, 1, 0);
+    while let Some(&(x, y)) = stack.last() {
+        let neighbours: Vec<(usize, usize)> = [(0, -1), (1, 0), (0, 1), (-1, 0)]
+            .iter()
+            .map(|&(dx, dy)| (x as isize + dx, y as isize + dy))
+            .filter(|&(nx, ny)| {
+                nx >= 0 && ny >= 0 && (nx as usize) < cells_x && (ny as usize) < cells_y
+            })
+            .map(|(nx, ny)| (nx as usize, ny as usize))
+            .filter(|&(nx, ny)| !visited[nx + ny * cells_x])
+            .collect();
+
+        if neighbours.is_empty() {
+            stack.pop();
+            continue;
+        }
+
+        let (nx, ny) = neighbours[rng.range(0, neighbours.len())];
+        visited[nx + ny * cells_x] = true;
+        //Clear the cell and the wall between it and the current one
+        grid.set(nx * 2 + 1, ny * 2 + 1, 0);
+        grid.set(x + nx + 1, y + ny + 1, 0);
+        stack.push((nx, ny));
+    }
+
+    //Vary the wall textures a little
+    for tile in grid.walls.iter_mut().filter(|tile| **tile != 0) {
+        if rng.chance(0.1) {
+            *tile = rng.range(2, 4) as u8;
+        }
+    }
+
+    grid
+}
+
+struct Room {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Room {
+    fn center(&self) -> (usize, usize) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    //Rooms must keep at least one wall between them
+    fn overlaps(&self, other: &Room) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+}
+
+//Places random rooms that do not overlap and joins each one to the previous
+//room with an L shaped corridor
+fn dungeon(width: usize, height: usize, rng: &mut Rng) -> Grid {
+    let mut grid = Grid::new(width, height, 2);
+    let max_size = ((width.min(height) - 2) / 2).clamp(3, 10);
+    let attempts = width * height / 10;
+
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..attempts {
+        let room_width = rng.range(3, max_size + 1).min(width - 2);
+        let room_height = rng.range(3, max_size + 1).min(height - 2);
+        let room = Room {
+            x: rng.range(1, width - room_width),
+            y: rng.range(1, height - room_height),
+            width: room_width,
+            height: room_height,
+        };
+        if rooms.iter().any(|other| other.overlaps(&room)) {
+            continue;
+        }
+
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                grid.set(x, y, 0);
+            }
+        }
+
+        if let Some(previous) = rooms.last() {
+            let (x1, y1) = previous.center();
+            let (x2, y2) = room.center();
+            //Go horizontally or vertically first at random
+            let corner = if rng.chance(0.5) { (x2, y1) } else { (x1, y2) };
+            for (from, to) in [((x1, y1), corner), (corner, (x2, y2))] {
+                for x in from.0.min(to.0)..=from.0.max(to.0) {
+                    for y in from.1.min(to.1)..=from.1.max(to.1) {
+                        grid.set(x, y, 0);
+                    }
+                }
+            }
+        }
+
+        rooms.push(room);
+    }
+
+    grid
+}
+
+//Starts from random noise and repeatedly turns cells into walls when most of
+//their neighbours are walls, then keeps only the largest open area so every
+//empty cell can be reached
+fn cave(width: usize, height: usize, rng: &mut Rng) -> Grid {
+    let mut grid = Grid::new(width, height, 3);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            if !rng.chance(0.45) {
+                grid.set(x, y, 0);
+            }
+        }
+    }
+
+    for _ in 0..5 {
+        let mut next = Grid::new(width, height, 3);
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let walls = (y - 1..=y + 1)
+                    .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                    .filter(|&(nx, ny)| grid.is_wall(nx, ny))
+                    .count();
+                if walls < 5 {
+                    next.set(x, y, 0);
+                }
+            }
+        }
+        grid = next;
+    }
+
+    //Flood fill every open area and remember the largest
+    let mut area = vec![usize::MAX; width * height];
+    let mut largest = (0, 0);
+    let mut area_count = 0;
+    for (x, y) in grid.empty_cells() {
+        if area[x + y * width] != usize::MAX {
+            continue;
+        }
+
+        let mut size = 0;
+        let mut stack = vec![(x, y)];
+        area[x + y * width] = area_count;
+        while let Some((cx, cy)) = stack.pop() {
+            size += 1;
+            for (nx, ny) in [(cx + 1, cy), (cx - 1, cy), (cx, cy + 1), (cx, cy - 1)] {
+                if !grid.is_wall(nx, ny) && area[nx + ny * width] == usize::MAX {
+                    area[nx + ny * width] = area_count;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+
+        if size > largest.1 {
+            largest = (area_count, size);
+        }
+        area_count += 1;
+    }
+
+    for (x, y) in grid.empty_cells() {
+        if area[x + y * width] != largest.0 {
+            grid.set(x, y, 3);
+        }
+    }
+
+    //A cave that closed up completely still needs somewhere to stand
+    if largest.1 == 0 {
+        grid.set(width / 2, height / 2, 0);
+    }
+
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Algorithm; 3] = [Algorithm::Maze, Algorithm::Dungeon, Algorithm::Cave];
+
+    //Everything a generator decides, as text so that two levels are easy to
+    //compare
+    fn describe(level: &Level) -> String {
+        let mut text = format!(
+            "{:?}\n{:?}\n{:?}\n",
+            level.walls, level.floor, level.ceiling
+        );
+        text += &format!(
+            "start {} {} {}\n",
+            level.start.x, level.start.y, level.start.angle
+        );
+        for sprite in &level.sprites {
+            text += &format!("sprite {} {} {}\n", sprite.kind, sprite.x, sprite.y);
+        }
+        text
+    }
+
+    #[test]
+    fn same_seed_same_level() {
+        for algorithm in ALGORITHMS {
+            let first = generate(algorithm, 40, 30, 1234).unwrap();
+            let second = generate(algorithm, 40, 30, 1234).unwrap();
+            assert_eq!(describe(&first), describe(&second), "{algorithm:?}");
+        }
+    }
+
+    #[test]
+    fn every_open_cell_is_reachable() {
+        for algorithm in ALGORITHMS {
+            for (width, height) in [(40, 30), (5, 5), (64, 7)] {
+                for seed in 0..8 {
+                    let level = generate(algorithm, width, height, seed).unwrap();
+                    let start = (
+                        level.start.x.floor() as isize,
+                        level.start.y.floor() as isize,
+                    );
+                    assert_eq!(level.get_tile(start.0, start.1), 0, "{algorithm:?} {seed}");
+
+                    let mut reached = vec![false; width * height];
+                    reached[start.0 as usize + start.1 as usize * width] = true;
+                    let mut stack = vec![start];
+                    while let Some((x, y)) = stack.pop() {
+                        for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                            let ind = nx as usize + ny as usize * width;
+                            if level.get_tile(nx, ny) == 0 && !reached[ind] {
+                                reached[ind] = true;
+                                stack.push((nx, ny));
+                            }
+                        }
+                    }
+
+                    for (ind, &tile) in level.walls.iter().enumerate() {
+                        assert!(
+                            tile != 0 || reached[ind],
+                            "{algorithm:?} {width}x{height} seed {seed}: cell {ind} is cut off"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
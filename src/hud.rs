@@ -0,0 +1,226 @@
+use crate::font::Font;
+use crate::framebuffer::FrameBuffer;
+use crate::player::Player;
+use crate::weapon::WeaponKind;
+use sdl2::pixels::Color;
+
+#[derive(Clone, Copy)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+//Placement of a block of text on the screen, x and y are measured from the
+//corner given by the anchor towards the center of the screen
+#[derive(Clone, Copy)]
+pub struct HudItem {
+    pub visible: bool,
+    pub anchor: Anchor,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl HudItem {
+    pub fn new(anchor: Anchor, x: i32, y: i32) -> HudItem {
+        HudItem {
+            visible: true,
+            anchor,
+            x,
+            y,
+        }
+    }
+}
+
+pub struct Hud {
+    pub visible: bool,
+    //Size of each font pixel on the screen
+    pub scale: usize,
+    pub color: Color,
+    pub shadow: Color,
+    pub fps: HudItem,
+    pub position: HudItem,
+    pub recording: HudItem,
+    //Health and armor on the left of the status bar, the weapon, its ammo
+    //and the keys carried on the right
+    pub status_left: HudItem,
+    pub status_right: HudItem,
+    pub status_bar: Color,
+    //Short notices such as a locked door, shown for a few seconds under the
+    //fps counter where the corner minimap does not cover them
+    pub message: HudItem,
+    pub message_duration: f64,
+    //Set while frames are being recorded to disk
+    pub is_recording: bool,
+    //The fps counter is averaged over half a second so that it is readable
+    frame_count: u32,
+    frame_time: f64,
+    fps_value: f64,
+    message_text: String,
+    message_time: f64,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud {
+            visible: true,
+            scale: 2,
+            color: Color::RGBA(255, 255, 255, 255),
+            shadow: Color::RGBA(0, 0, 0, 255),
+            fps: HudItem::new(Anchor::TopRight, 8, 8),
+            position: HudItem::new(Anchor::BottomLeft, 8, 48),
+            recording: HudItem::new(Anchor::TopRight, 8, 28),
+            status_left: HudItem::new(Anchor::BottomLeft, 8, 8),
+            status_right: HudItem::new(Anchor::BottomRight, 8, 8),
+            status_bar: Color::RGBA(0, 0, 0, 140),
+            message: HudItem::new(Anchor::TopRight, 8, 48),
+            message_duration: 2.0,
+            is_recording: false,
+            frame_count: 0,
+            frame_time: 0.0,
+            fps_value: 0.0,
+            message_text: String::new(),
+            message_time: 0.0,
+        }
+    }
+
+    pub fn show_message(&mut self, text: String) {
+        self.message_text = text;
+        self.message_time = self.message_duration;
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.message_time = (self.message_time - dt).max(0.0);
+
+        self.frame_count += 1;
+        self.frame_time += dt;
+        if self.frame_time >= 0.5 {
+            self.fps_value = self.frame_count as f64 / self.frame_time;
+            self.frame_count = 0;
+            self.frame_time = 0.0;
+        }
+    }
+
+    //Draws lines of text at the position of `item`
+    pub fn draw_item(
+        &self,
+        font: &Font,
+        framebuffer: &mut FrameBuffer,
+        item: &HudItem,
+        lines: &[String],
+    ) {
+        if !self.visible || !item.visible {
+            return;
+        }
+
+        let width = framebuffer.width() as i32;
+        let height = framebuffer.height() as i32;
+        let line_height = (font.glyph_height * self.scale) as i32;
+        let block_height = line_height * lines.len() as i32;
+
+        for (i, line) in lines.iter().enumerate() {
+            let line_width = font.text_width(line, self.scale) as i32;
+            let x = match item.anchor {
+                Anchor::TopLeft | Anchor::BottomLeft => item.x,
+                Anchor::TopRight | Anchor::BottomRight => width - item.x - line_width,
+            };
+            let y = match item.anchor {
+                Anchor::TopLeft | Anchor::TopRight => item.y,
+                Anchor::BottomLeft | Anchor::BottomRight => height - item.y - block_height,
+            } + line_height * i as i32;
+
+            let offset = self.scale as i32 / 2 + 1;
+            font.draw_text(
+                framebuffer,
+                x + offset,
+                y + offset,
+                line,
+                self.scale,
+                self.shadow,
+            );
+            font.draw_text(framebuffer, x, y, line, self.scale, self.color);
+        }
+    }
+
+    //Draws a band across the bottom of the screen with the state of the
+    //player and the weapon in hand
+    pub fn draw_status(
+        &self,
+        font: &Font,
+        framebuffer: &mut FrameBuffer,
+        player: &Player,
+        weapon: &WeaponKind,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let bar_height = (font.glyph_height * self.scale + 16) as i32;
+        let height = framebuffer.height() as i32;
+        for y in height - bar_height..height {
+            for x in 0..framebuffer.width() as i32 {
+                framebuffer.set_pixel(x, y, self.status_bar);
+            }
+        }
+
+        self.draw_item(
+            font,
+            framebuffer,
+            &self.status_left,
+            &[format!("HEALTH {}  ARMOR {}", player.health, player.armor)],
+        );
+        let keys: String = player
+            .inventory
+            .iter()
+            .filter_map(|item| item.strip_prefix("key_"))
+            .map(|key| format!("  {}", key.to_uppercase()))
+            .collect();
+        self.draw_item(
+            font,
+            framebuffer,
+            &self.status_right,
+            &[format!(
+                "{} {}{keys}",
+                weapon.name,
+                player.ammo(weapon.ammo)
+            )],
+        );
+    }
+
+    pub fn draw(
+        &self,
+        font: &Font,
+        framebuffer: &mut FrameBuffer,
+        camx: f64,
+        camy: f64,
+        cam_rotation: f64,
+    ) {
+        self.draw_item(
+            font,
+            framebuffer,
+            &self.fps,
+            &[format!("FPS: {:.0}", self.fps_value)],
+        );
+        self.draw_item(
+            font,
+            framebuffer,
+            &self.position,
+            &[
+                format!("X: {camx:.2} Y: {camy:.2}"),
+                format!("ANGLE: {:.1}", cam_rotation.to_degrees()),
+            ],
+        );
+        if self.message_time > 0.0 {
+            self.draw_item(
+                font,
+                framebuffer,
+                &self.message,
+                std::slice::from_ref(&self.message_text),
+            );
+        }
+        if self.is_recording {
+            self.draw_item(font, framebuffer, &self.recording, &[String::from("REC")]);
+        }
+    }
+}
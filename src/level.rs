@@ -0,0 +1,893 @@
+use crate::error::Error;
+use crate::event::{Action, Handler, Switch, Trigger};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//Push walls slide this many cells unless something is in the way
+const PUSH_DISTANCE: isize = 2;
+//Cells per second
+const PUSH_SPEED: f64 = 1.0;
+
+//Where the camera starts, angle is in radians
+#[derive(Clone, Copy)]
+pub struct PlayerStart {
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+}
+
+//A billboard sprite placed in the level, `kind` says what it is (for
+//example "decoration" or "enemy")
+#[derive(Clone)]
+pub struct LevelSprite {
+    pub kind: String,
+    pub x: f64,
+    pub y: f64,
+}
+
+//A point light, brightness falls off linearly to nothing at `radius` cells
+#[derive(Clone, Copy)]
+pub struct Light {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+    pub intensity: f64,
+}
+
+//Brightness of places no light reaches, only used when a level has lights
+const AMBIENT_LIGHT: f64 = 0.25;
+
+//Levels can be at most this many cells wide and high
+pub const MAX_SIZE: usize = 1024;
+
+//Number of 16x16 tiles side by side in textures.png, tile ids count from 1
+pub const TILE_COUNT: u8 = 4;
+
+//Revisions are counted across all levels so that two different levels, or
+//two states of one, never share a revision
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+//Checks a level size before anything of that size is allocated
+fn check_size(width: usize, height: usize) -> Result<(), Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidLevel(format!(
+            "level size must not be zero ({width}x{height})"
+        )));
+    }
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(Error::InvalidLevel(format!(
+            "level size must be at most {MAX_SIZE}x{MAX_SIZE} ({width}x{height})"
+        )));
+    }
+    Ok(())
+}
+
+//Color of the key a locked door needs
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Key {
+    Gold,
+    Silver,
+}
+
+impl Key {
+    //Name used in level files
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::Gold => "gold",
+            Key::Silver => "silver",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        match name {
+            "gold" => Some(Key::Gold),
+            "silver" => Some(Key::Silver),
+            _ => None,
+        }
+    }
+
+    //Kind of the sprite that gives the player this key
+    pub fn item(self) -> &'static str {
+        match self {
+            Key::Gold => "key_gold",
+            Key::Silver => "key_silver",
+        }
+    }
+}
+
+//A wall cell that can be opened. While the door is open its cell in the walls
+//layer is empty and `tile` remembers the texture to restore when it closes.
+#[derive(Clone, Copy)]
+pub struct Door {
+    pub x: usize,
+    pub y: usize,
+    pub tile: u8,
+    pub open: bool,
+    //Key needed to open the door
+    pub lock: Option<Key>,
+}
+
+//A wall that slides away when it is pushed, see `Level::push_wall`. While it
+//moves it is not in the walls layer, `block` is where it is drawn and
+//collides instead.
+#[derive(Clone, Copy)]
+pub struct PushWall {
+    pub x: usize,
+    pub y: usize,
+    pub tile: u8,
+    //One cell along x or y, the way it is pushed
+    pub dir: (isize, isize),
+    //Cells moved so far out of the cells it moves in total, both stay 0
+    //until it is pushed
+    pub offset: f64,
+    pub distance: f64,
+}
+
+impl PushWall {
+    pub fn is_moving(&self) -> bool {
+        self.offset < self.distance
+    }
+
+    //Top left corner of the wall while it is moving
+    pub fn block(&self) -> Option<(f64, f64)> {
+        if !self.is_moving() {
+            return None;
+        }
+
+        Some((
+            self.x as f64 + self.dir.0 as f64 * self.offset,
+            self.y as f64 + self.dir.1 as f64 * self.offset,
+        ))
+    }
+
+    //True while it is moving through or towards the cell (x, y), the cells
+    //between where it started and where it stops are kept clear for it
+    pub fn crosses(&self, x: isize, y: isize) -> bool {
+        if !self.is_moving() {
+            return false;
+        }
+
+        let (endx, endy) = self.end();
+        let (startx, starty) = (self.x as isize, self.y as isize);
+        (startx.min(endx)..=startx.max(endx)).contains(&x)
+            && (starty.min(endy)..=starty.max(endy)).contains(&y)
+    }
+
+    //The cell it ends up in once pushed
+    fn end(&self) -> (isize, isize) {
+        let distance = self.distance as isize;
+        (
+            self.x as isize + self.dir.0 * distance,
+            self.y as isize + self.dir.1 * distance,
+        )
+    }
+}
+
+//One of the tile layers of a level
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Walls,
+    Floor,
+    Ceiling,
+}
+
+//Tile layers of a level, each layer is stored row by row. A wall tile of 0 is
+//empty space, floor and ceiling tiles pick the texture drawn in that cell.
+#[derive(Clone)]
+pub struct Level {
+    pub width: usize,
+    pub height: usize,
+    pub walls: Vec<u8>,
+    pub floor: Vec<u8>,
+    pub ceiling: Vec<u8>,
+    pub start: PlayerStart,
+    pub sprites: Vec<LevelSprite>,
+    pub doors: Vec<Door>,
+    pub pushwalls: Vec<PushWall>,
+    pub lights: Vec<Light>,
+    pub triggers: Vec<Trigger>,
+    pub switches: Vec<Switch>,
+    pub handlers: Vec<Handler>,
+    //Changes whenever a wall, door or push wall does, code that changes
+    //`walls`, `doors` or `pushwalls` itself must call `changed`
+    revision: u64,
+}
+
+impl Level {
+    pub fn new(
+        width: usize,
+        height: usize,
+        walls: Vec<u8>,
+        floor: Vec<u8>,
+        ceiling: Vec<u8>,
+    ) -> Result<Level, Error> {
+        check_size(width, height)?;
+
+        for (name, layer) in [("walls", &walls), ("floor", &floor), ("ceiling", &ceiling)] {
+            if layer.len() != width * height {
+                return Err(Error::InvalidLevel(format!(
+                    "{name} layer has {} tiles, expected {}",
+                    layer.len(),
+                    width * height
+                )));
+            }
+        }
+
+        //Start in the first empty cell unless the level says otherwise
+        let first_empty = walls.iter().position(|&tile| tile == 0).unwrap_or(0);
+        let start = PlayerStart {
+            x: (first_empty % width) as f64 + 0.5,
+            y: (first_empty / width) as f64 + 0.5,
+            angle: 0.0,
+        };
+
+        Ok(Level {
+            width,
+            height,
+            walls,
+            floor,
+            ceiling,
+            start,
+            sprites: Vec::new(),
+            doors: Vec::new(),
+            pushwalls: Vec::new(),
+            lights: Vec::new(),
+            triggers: Vec::new(),
+            switches: Vec::new(),
+            handlers: Vec::new(),
+            revision: NEXT_REVISION.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    //Caches built from the walls and doors of a level compare this to find
+    //out that they are out of date
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn changed(&mut self) {
+        self.revision = NEXT_REVISION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    //The level built into the demo, assets/demo.lvl
+    pub fn default_level() -> Result<Level, Error> {
+        Level::parse(include_str!("../assets/demo.lvl"))
+    }
+
+    //Turns the wall at (x, y) into a closed door, locked with `lock`
+    pub fn add_door(&mut self, x: usize, y: usize, lock: Option<Key>) -> Result<(), Error> {
+        let tile = self.get_tile(x as isize, y as isize);
+        if tile == 0 {
+            return Err(Error::InvalidLevel(format!(
+                "door at ({x}, {y}) is not on a wall tile"
+            )));
+        }
+
+        self.doors.push(Door {
+            x,
+            y,
+            tile,
+            open: false,
+            lock,
+        });
+        self.changed();
+        Ok(())
+    }
+
+    pub fn door_at(&self, x: isize, y: isize) -> Option<&Door> {
+        self.doors
+            .iter()
+            .find(|door| door.x as isize == x && door.y as isize == y)
+    }
+
+    //Opens a closed door or closes an open one, returns false if there is no
+    //door at (x, y)
+    pub fn toggle_door(&mut self, x: isize, y: isize) -> bool {
+        let ind = match self.index(x, y) {
+            Some(ind) => ind,
+            None => return false,
+        };
+
+        match self
+            .doors
+            .iter_mut()
+            .find(|door| door.x as isize == x && door.y as isize == y)
+        {
+            Some(door) => {
+                door.open = !door.open;
+                self.walls[ind] = if door.open { 0 } else { door.tile };
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    //Turns the wall at (x, y) into a push wall
+    pub fn add_pushwall(&mut self, x: usize, y: usize) -> Result<(), Error> {
+        let tile = self.get_tile(x as isize, y as isize);
+        if tile == 0 {
+            return Err(Error::InvalidLevel(format!(
+                "push wall at ({x}, {y}) is not on a wall tile"
+            )));
+        }
+
+        self.pushwalls.push(PushWall {
+            x,
+            y,
+            tile,
+            dir: (0, 0),
+            offset: 0.0,
+            distance: 0.0,
+        });
+        self.changed();
+        Ok(())
+    }
+
+    //Starts moving the push wall at (x, y) one cell at a time along `dir`
+    //into the empty cells behind it, stopping before any cell with one of
+    //`occupants` (the positions of the player, actors and items) in it.
+    //Returns false if there is no push wall there that can move.
+    pub fn push_wall(
+        &mut self,
+        x: isize,
+        y: isize,
+        dir: (isize, isize),
+        occupants: &[(f64, f64)],
+    ) -> bool {
+        let pushwall =
+            match self.pushwalls.iter().position(|wall| {
+                wall.x as isize == x && wall.y as isize == y && wall.distance == 0.0
+            }) {
+                Some(pushwall) => pushwall,
+                None => return false,
+            };
+
+        let mut distance = 0;
+        while distance < PUSH_DISTANCE {
+            let (cellx, celly) = (x + dir.0 * (distance + 1), y + dir.1 * (distance + 1));
+            if self.index(cellx, celly).is_none()
+                || self.get_tile(cellx, celly) != 0
+                || self.door_at(cellx, celly).is_some()
+                || self.pushwalls.iter().any(|wall| wall.crosses(cellx, celly))
+                || occupants.iter().any(|&(occupantx, occupanty)| {
+                    (occupantx.floor() as isize, occupanty.floor() as isize) == (cellx, celly)
+                })
+            {
+                break;
+            }
+            distance += 1;
+        }
+        if distance == 0 {
+            return false;
+        }
+
+        let ind = x as usize + y as usize * self.width;
+        self.walls[ind] = 0;
+        let pushwall = &mut self.pushwalls[pushwall];
+        pushwall.dir = dir;
+        pushwall.distance = distance as f64;
+        self.changed();
+        true
+    }
+
+    //Moves the push walls on by `dt` seconds, a wall that stops becomes part
+    //of the walls layer again
+    pub fn update_pushwalls(&mut self, dt: f64) {
+        let mut stopped = false;
+        for pushwall in self.pushwalls.iter_mut() {
+            if !pushwall.is_moving() {
+                continue;
+            }
+
+            pushwall.offset = (pushwall.offset + PUSH_SPEED * dt).min(pushwall.distance);
+            if !pushwall.is_moving() {
+                let (x, y) = pushwall.end();
+                self.walls[x as usize + y as usize * self.width] = pushwall.tile;
+                stopped = true;
+            }
+        }
+        if stopped {
+            self.changed();
+        }
+    }
+
+    //Turns the wall at (x, y) into a switch that fires `event`
+    pub fn add_switch(&mut self, x: usize, y: usize, event: &str) -> Result<(), Error> {
+        if self.get_tile(x as isize, y as isize) == 0 {
+            return Err(Error::InvalidLevel(format!(
+                "switch at ({x}, {y}) is not on a wall tile"
+            )));
+        }
+
+        self.switches.push(Switch {
+            x,
+            y,
+            event: String::from(event),
+            on: false,
+        });
+        Ok(())
+    }
+
+    pub fn switch_at(&self, x: isize, y: isize) -> Option<&Switch> {
+        self.switches
+            .iter()
+            .find(|switch| switch.x as isize == x && switch.y as isize == y)
+    }
+
+    //Flips the switch at (x, y) and returns the event it fires, None if there
+    //is no switch there
+    pub fn use_switch(&mut self, x: isize, y: isize) -> Option<String> {
+        let switch = self
+            .switches
+            .iter_mut()
+            .find(|switch| switch.x as isize == x && switch.y as isize == y)?;
+        switch.on = !switch.on;
+        Some(switch.event.clone())
+    }
+
+    //Reads a level file, see `Level::parse` for the format
+    pub fn load(path: &Path) -> Result<Level, Error> {
+        let text = fs::read_to_string(path).map_err(|source| Error::AssetNotFound {
+            path: PathBuf::from(path),
+            source,
+        })?;
+
+        Level::parse(&text).map_err(|err| match err {
+            Error::InvalidLevel(msg) => Error::InvalidLevel(format!("{}: {msg}", path.display())),
+            err => err,
+        })
+    }
+
+    //Level files are plain text, blank lines and lines starting with # are
+    //ignored. `size <width> <height>` must come first, it is followed by the
+    //`walls`, `floor` and `ceiling` layers: the layer name on its own line and
+    //then one line of tile ids per row. Objects are one per line:
+    //`start <x> <y> <angle in degrees>`, `door <x> <y> [gold|silver]` on a
+    //wall tile with the key it needs if it is locked, `pushwall <x> <y>` on a
+    //wall tile, `sprite <kind> <x> <y>` and
+    //`light <x> <y> <radius> <intensity>`.
+    //
+    //Events are named by the level, `event <name> <action>` adds an action to
+    //the event (see `Action::parse`) and an event with several actions runs
+    //them in the order they are given. Actions must point inside the level
+    //and teleports into an empty cell. `trigger <x> <y> <width> <height>
+    //<event> [once]` fires the event whenever the player walks into the area,
+    //or only the first time with `once`. `switch <x> <y> <event>` on a wall
+    //tile fires the event every time the player uses it.
+    //
+    //  size 3 3
+    //  walls
+    //  1 1 1
+    //  1 0 1
+    //  1 1 1
+    //  start 1.5 1.5 90
+    pub fn parse(text: &str) -> Result<Level, Error> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let invalid =
+            |line_num: usize, msg: &str| Error::InvalidLevel(format!("line {line_num}: {msg}"));
+
+        let (width, height) = match lines.next() {
+            Some((line_num, line)) => {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words.as_slice() {
+                    ["size", width, height] => (
+                        width
+                            .parse::<usize>()
+                            .map_err(|_| invalid(line_num, "invalid width"))?,
+                        height
+                            .parse::<usize>()
+                            .map_err(|_| invalid(line_num, "invalid height"))?,
+                    ),
+                    _ => return Err(invalid(line_num, "expected size <width> <height>")),
+                }
+            }
+            None => return Err(Error::InvalidLevel(String::from("empty level file"))),
+        };
+        check_size(width, height)?;
+
+        let parse_f64 = |line_num: usize, value: &str| {
+            value
+                .parse::<f64>()
+                .map_err(|_| invalid(line_num, &format!("invalid number: {value}")))
+        };
+
+        let mut walls = Vec::new();
+        let mut floor = Vec::new();
+        let mut ceiling = Vec::new();
+        let mut start = None;
+        let mut sprites = Vec::new();
+        let mut doors = Vec::new();
+        let mut pushwalls = Vec::new();
+        let mut lights = Vec::new();
+        let mut triggers = Vec::new();
+        let mut switches = Vec::new();
+        let mut handlers = Vec::new();
+        while let Some((line_num, line)) = lines.next() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let layer = match words.as_slice() {
+                ["walls"] => &mut walls,
+                ["floor"] => &mut floor,
+                ["ceiling"] => &mut ceiling,
+                ["start", x, y, angle] => {
+                    start = Some(PlayerStart {
+                        x: parse_f64(line_num, x)?,
+                        y: parse_f64(line_num, y)?,
+                        angle: parse_f64(line_num, angle)?.to_radians(),
+                    });
+                    continue;
+                }
+                ["sprite", kind, x, y] => {
+                    sprites.push(LevelSprite {
+                        kind: kind.to_string(),
+                        x: parse_f64(line_num, x)?,
+                        y: parse_f64(line_num, y)?,
+                    });
+                    continue;
+                }
+                ["light", x, y, radius, intensity] => {
+                    lights.push(Light {
+                        x: parse_f64(line_num, x)?,
+                        y: parse_f64(line_num, y)?,
+                        radius: parse_f64(line_num, radius)?,
+                        intensity: parse_f64(line_num, intensity)?,
+                    });
+                    continue;
+                }
+                ["door", x, y, lock @ ..] if lock.len() <= 1 => {
+                    let x = x
+                        .parse::<usize>()
+                        .map_err(|_| invalid(line_num, "invalid door x"))?;
+                    let y = y
+                        .parse::<usize>()
+                        .map_err(|_| invalid(line_num, "invalid door y"))?;
+                    let lock =
+                        match lock.first() {
+                            Some(name) => Some(Key::from_name(name).ok_or_else(|| {
+                                invalid(line_num, &format!("unknown key: {name}"))
+                            })?),
+                            None => None,
+                        };
+                    doors.push((line_num, x, y, lock));
+                    continue;
+                }
+                ["pushwall", x, y] => {
+                    let x = x
+                        .parse::<usize>()
+                        .map_err(|_| invalid(line_num, "invalid push wall x"))?;
+                    let y = y
+                        .parse::<usize>()
+                        .map_err(|_| invalid(line_num, "invalid push wall y"))?;
+                    pushwalls.push((line_num, x, y));
+                    continue;
+                }
+                ["event", event, action @ ..] => {
+                    handlers.push((
+                        line_num,
+                        Handler {
+                            event: event.to_string(),
+                            action: Action::parse(action).map_err(|msg| invalid(line_num, &msg))?,
+                        },
+                    ));
+                    continue;
+                }
+                ["trigger", x, y, width, height, event, once @ ..]
+                    if once.is_empty() || once == ["once"] =>
+                {
+                    triggers.push((
+                        line_num,
+                        Trigger {
+                            x: parse_f64(line_num, x)?,
+                            y: parse_f64(line_num, y)?,
+                            width: parse_f64(line_num, width)?,
+                            height: parse_f64(line_num, height)?,
+                            event: event.to_string(),
+                            once: !once.is_empty(),
+                        },
+                    ));
+                    continue;
+                }
+                ["switch", x, y, event] => {
+                    let x = x
+                        .parse::<usize>()
+                        .map_err(|_| invalid(line_num, "invalid switch x"))?;
+                    let y = y
+                        .parse::<usize>()
+                        .map_err(|_| invalid(line_num, "invalid switch y"))?;
+                    switches.push((line_num, x, y, event.to_string()));
+                    continue;
+                }
+                _ => return Err(invalid(line_num, &format!("unknown section: {line}"))),
+            };
+
+            if !layer.is_empty() {
+                return Err(invalid(line_num, &format!("duplicate section: {line}")));
+            }
+
+            for _ in 0..height {
+                let (line_num, row) = lines
+                    .next()
+                    .ok_or_else(|| Error::InvalidLevel(format!("{line} layer is too short")))?;
+                let row = row
+                    .split_whitespace()
+                    .map(|tile| tile.parse::<u8>())
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| invalid(line_num, "invalid tile id"))?;
+                if let Some(tile) = row.iter().find(|&&tile| tile > TILE_COUNT) {
+                    return Err(invalid(
+                        line_num,
+                        &format!("tile id {tile} is out of range, the last is {TILE_COUNT}"),
+                    ));
+                }
+                if row.len() != width {
+                    return Err(invalid(
+                        line_num,
+                        &format!("expected {width} tiles, found {}", row.len()),
+                    ));
+                }
+                layer.extend(row);
+            }
+        }
+
+        //Missing floor and ceiling layers are left empty
+        if floor.is_empty() {
+            floor = vec![0; width * height];
+        }
+        if ceiling.is_empty() {
+            ceiling = vec![0; width * height];
+        }
+
+        let mut level = Level::new(width, height, walls, floor, ceiling)?;
+        if let Some(start) = start {
+            level.start = start;
+        }
+        level.sprites = sprites;
+        level.lights = lights;
+        for (line_num, x, y, lock) in doors {
+            level
+                .add_door(x, y, lock)
+                .map_err(|_| invalid(line_num, "door is not on a wall tile"))?;
+        }
+        for (line_num, x, y) in pushwalls {
+            level
+                .add_pushwall(x, y)
+                .map_err(|_| invalid(line_num, "push wall is not on a wall tile"))?;
+        }
+
+        for (line_num, handler) in &handlers {
+            let (x, y) = match &handler.action {
+                Action::OpenDoor { x, y } | Action::SetTile { x, y, .. } => (*x as f64, *y as f64),
+                Action::Spawn(LevelSprite { x, y, .. })
+                | Action::Teleport(PlayerStart { x, y, .. }) => (*x, *y),
+                Action::EndLevel => continue,
+            };
+            if !(0.0..width as f64).contains(&x) || !(0.0..height as f64).contains(&y) {
+                return Err(invalid(
+                    *line_num,
+                    &format!("({x}, {y}) is outside the level"),
+                ));
+            }
+            match handler.action {
+                Action::SetTile { tile, .. } if tile > TILE_COUNT => {
+                    return Err(invalid(
+                        *line_num,
+                        &format!("tile id {tile} is out of range, the last is {TILE_COUNT}"),
+                    ));
+                }
+                Action::Teleport(_) if level.get_tile(x as isize, y as isize) != 0 => {
+                    return Err(invalid(*line_num, "teleport lands in a wall"));
+                }
+                _ => {}
+            }
+        }
+
+        //Triggers and switches must fire events the level has
+        let check_event = |line_num: usize, event: &str| {
+            if handlers.iter().any(|(_, handler)| handler.event == event) {
+                Ok(())
+            } else {
+                Err(invalid(line_num, &format!("unknown event: {event}")))
+            }
+        };
+        for (line_num, trigger) in triggers {
+            check_event(line_num, &trigger.event)?;
+            level.triggers.push(trigger);
+        }
+        for (line_num, x, y, event) in switches {
+            check_event(line_num, &event)?;
+            level
+                .add_switch(x, y, &event)
+                .map_err(|_| invalid(line_num, "switch is not on a wall tile"))?;
+        }
+        level.handlers = handlers.into_iter().map(|(_, handler)| handler).collect();
+        Ok(level)
+    }
+
+    //How brightly lit the point (x, y) is from 0 to 1, levels without any
+    //lights are fully lit everywhere
+    pub fn light_at(&self, x: f64, y: f64) -> f64 {
+        if self.lights.is_empty() {
+            return 1.0;
+        }
+
+        let light: f64 = self
+            .lights
+            .iter()
+            .map(|light| {
+                let dist = ((light.x - x).powi(2) + (light.y - y).powi(2)).sqrt();
+                light.intensity * (1.0 - dist / light.radius).max(0.0)
+            })
+            .sum();
+        (AMBIENT_LIGHT + light).min(1.0)
+    }
+
+    fn index(&self, x: isize, y: isize) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+
+        Some(x as usize + y as usize * self.width)
+    }
+
+    //True if (x, y) is inside a wall, moving push walls included
+    pub fn is_solid(&self, x: f64, y: f64) -> bool {
+        self.get_tile(x.floor() as isize, y.floor() as isize) != 0
+            || self
+                .pushwalls
+                .iter()
+                .filter_map(PushWall::block)
+                .any(|(left, top)| (left..left + 1.0).contains(&x) && (top..top + 1.0).contains(&y))
+    }
+
+    //Anything outside of the level is empty
+    pub fn get_tile(&self, x: isize, y: isize) -> u8 {
+        self.index(x, y).map(|i| self.walls[i]).unwrap_or(0)
+    }
+
+    pub fn get_floor(&self, x: isize, y: isize) -> u8 {
+        self.index(x, y).map(|i| self.floor[i]).unwrap_or(0)
+    }
+
+    pub fn get_ceil(&self, x: isize, y: isize) -> u8 {
+        self.index(x, y).map(|i| self.ceiling[i]).unwrap_or(0)
+    }
+
+    pub fn layer(&self, layer: Layer) -> &[u8] {
+        match layer {
+            Layer::Walls => &self.walls,
+            Layer::Floor => &self.floor,
+            Layer::Ceiling => &self.ceiling,
+        }
+    }
+
+    //Changes one tile, returns false if (x, y) is outside of the level. A door
+    //or push wall painted over with another wall keeps working with the new
+    //texture and one that is cleared is removed, as are switches.
+    pub fn set_tile(&mut self, layer: Layer, x: isize, y: isize, tile: u8) -> bool {
+        let ind = match self.index(x, y) {
+            Some(ind) => ind,
+            None => return false,
+        };
+
+        match layer {
+            Layer::Walls => {
+                if tile == 0 {
+                    self.doors
+                        .retain(|door| door.x as isize != x || door.y as isize != y);
+                } else if let Some(door) = self
+                    .doors
+                    .iter_mut()
+                    .find(|door| door.x as isize == x && door.y as isize == y)
+                {
+                    door.tile = tile;
+                    door.open = false;
+                }
+                if tile == 0 {
+                    self.pushwalls
+                        .retain(|wall| wall.x as isize != x || wall.y as isize != y);
+                } else if let Some(pushwall) = self
+                    .pushwalls
+                    .iter_mut()
+                    .find(|wall| wall.x as isize == x && wall.y as isize == y)
+                {
+                    pushwall.tile = tile;
+                }
+                if tile == 0 {
+                    self.switches
+                        .retain(|switch| switch.x as isize != x || switch.y as isize != y);
+                }
+                self.walls[ind] = tile;
+                self.changed();
+            }
+            Layer::Floor => self.floor[ind] = tile,
+            Layer::Ceiling => self.ceiling[ind] = tile,
+        }
+        true
+    }
+
+    //The level in the format read by `Level::parse`, doors are saved closed,
+    //push walls where they started and switches off
+    pub fn to_text(&self) -> String {
+        let mut walls = self.walls.clone();
+        for door in &self.doors {
+            walls[door.x + door.y * self.width] = door.tile;
+        }
+        for pushwall in &self.pushwalls {
+            if pushwall.distance > 0.0 && !pushwall.is_moving() {
+                let (x, y) = pushwall.end();
+                walls[x as usize + y as usize * self.width] = 0;
+            }
+            walls[pushwall.x + pushwall.y * self.width] = pushwall.tile;
+        }
+
+        let mut text = format!("size {} {}\n", self.width, self.height);
+        for (name, layer) in [
+            ("walls", &walls),
+            ("floor", &self.floor),
+            ("ceiling", &self.ceiling),
+        ] {
+            text += &format!("\n{name}\n");
+            for row in layer.chunks(self.width) {
+                let row: Vec<String> = row.iter().map(|tile| tile.to_string()).collect();
+                text += &row.join(" ");
+                text.push('\n');
+            }
+        }
+
+        text += &format!(
+            "\nstart {} {} {}\n",
+            self.start.x,
+            self.start.y,
+            self.start.angle.to_degrees()
+        );
+        for door in &self.doors {
+            text += &format!("door {} {}", door.x, door.y);
+            if let Some(key) = door.lock {
+                text += &format!(" {}", key.name());
+            }
+            text.push('\n');
+        }
+        for pushwall in &self.pushwalls {
+            text += &format!("pushwall {} {}\n", pushwall.x, pushwall.y);
+        }
+        for handler in &self.handlers {
+            text += &format!("event {} {}\n", handler.event, handler.action.to_text());
+        }
+        for trigger in &self.triggers {
+            text += &format!(
+                "trigger {} {} {} {} {}",
+                trigger.x, trigger.y, trigger.width, trigger.height, trigger.event
+            );
+            if trigger.once {
+                text += " once";
+            }
+            text.push('\n');
+        }
+        for switch in &self.switches {
+            text += &format!("switch {} {} {}\n", switch.x, switch.y, switch.event);
+        }
+        for sprite in &self.sprites {
+            text += &format!("sprite {} {} {}\n", sprite.kind, sprite.x, sprite.y);
+        }
+        for light in &self.lights {
+            text += &format!(
+                "light {} {} {} {}\n",
+                light.x, light.y, light.radius, light.intensity
+            );
+        }
+        text
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_text()).map_err(|source| Error::Io {
+            path: PathBuf::from(path),
+            source,
+        })
+    }
+}
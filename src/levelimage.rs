@@ -0,0 +1,207 @@
+use crate::bitmap::BitMap;
+use crate::capture;
+use crate::error::Error;
+use crate::level::{Level, PlayerStart};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//Maps pixel colors of a level image to wall tiles. Cells without a wall get
+//`floor` and `ceiling` tiles, a pixel of `start` color is an empty cell where
+//the player starts.
+#[derive(Clone)]
+pub struct Palette {
+    pub colors: Vec<([u8; 3], u8)>,
+    pub start: [u8; 3],
+    pub floor: u8,
+    pub ceiling: u8,
+}
+
+impl Default for Palette {
+    //White is empty and the walls match the minimap colors
+    fn default() -> Palette {
+        Palette {
+            colors: vec![
+                ([255, 255, 255], 0),
+                ([160, 160, 160], 1),
+                ([170, 90, 60], 2),
+                ([70, 130, 200], 3),
+                ([80, 170, 80], 4),
+            ],
+            start: [0, 255, 0],
+            floor: 2,
+            ceiling: 1,
+        }
+    }
+}
+
+//Colors are written as rrggbb in hex, there is no leading # as that starts a
+//comment
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    if text.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(text.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl Palette {
+    //Palette files have one entry per line, blank lines and lines starting
+    //with # are ignored: `<rrggbb> <tile>` maps a color to a wall tile (0 for
+    //empty), `start <rrggbb>`, `floor <tile>` and `ceiling <tile>` replace the
+    //defaults.
+    pub fn load(path: &Path) -> Result<Palette, Error> {
+        let text = fs::read_to_string(path).map_err(|source| Error::AssetNotFound {
+            path: PathBuf::from(path),
+            source,
+        })?;
+        Palette::parse(&text)
+            .map_err(|msg| Error::InvalidLevel(format!("{}: {msg}", path.display())))
+    }
+
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        let mut palette = Palette {
+            colors: Vec::new(),
+            ..Palette::default()
+        };
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |what: &str| format!("line {}: invalid {what}", line_num + 1);
+            let tile = |value: &str| value.parse::<u8>().map_err(|_| invalid("tile id"));
+            match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                ["start", color] => {
+                    palette.start = parse_color(color).ok_or_else(|| invalid("color"))?
+                }
+                ["floor", value] => palette.floor = tile(value)?,
+                ["ceiling", value] => palette.ceiling = tile(value)?,
+                [color, value] => palette.colors.push((
+                    parse_color(color).ok_or_else(|| invalid("color"))?,
+                    tile(value)?,
+                )),
+                _ => return Err(format!("line {}: expected <rrggbb> <tile>", line_num + 1)),
+            }
+        }
+
+        Ok(palette)
+    }
+
+    fn tile(&self, color: [u8; 3]) -> Option<u8> {
+        self.colors
+            .iter()
+            .find(|(entry, _)| *entry == color)
+            .map(|&(_, tile)| tile)
+    }
+
+    fn color(&self, tile: u8) -> Option<[u8; 3]> {
+        self.colors
+            .iter()
+            .find(|&&(_, entry)| entry == tile)
+            .map(|&(color, _)| color)
+    }
+
+    //Made up color for a tile the palette has none for, always the same one
+    //for a tile and never one the palette already uses
+    fn fallback_color(&self, tile: u8) -> [u8; 3] {
+        let mut hash = tile as u32;
+        loop {
+            hash = hash.wrapping_mul(0x9e37_79b1).wrapping_add(0x7f4a_7c15);
+            let color = [(hash >> 24) as u8, (hash >> 16) as u8, (hash >> 8) as u8];
+            if color != self.start && self.tile(color).is_none() {
+                return color;
+            }
+        }
+    }
+}
+
+//Builds a level with one cell per pixel, fully transparent pixels are empty.
+//`path` is only used to report errors.
+pub fn from_bitmap(bitmap: &BitMap, palette: &Palette, path: &Path) -> Result<Level, Error> {
+    let mut walls = Vec::with_capacity(bitmap.width * bitmap.height);
+    let mut start = None;
+    for y in 0..bitmap.height {
+        for x in 0..bitmap.width {
+            let pixel = bitmap.pixel(x, y);
+            let color = [pixel[0], pixel[1], pixel[2]];
+            let tile = if pixel[3] == 0 {
+                0
+            } else if color == palette.start {
+                start = Some(PlayerStart {
+                    x: x as f64 + 0.5,
+                    y: y as f64 + 0.5,
+                    angle: 0.0,
+                });
+                0
+            } else {
+                palette.tile(color).ok_or_else(|| {
+                    Error::InvalidLevel(format!(
+                        "{}: pixel ({x}, {y}) has color {:02x}{:02x}{:02x} which is not in the palette",
+                        path.display(),
+                        color[0],
+                        color[1],
+                        color[2]
+                    ))
+                })?
+            };
+            walls.push(tile);
+        }
+    }
+
+    let floor = walls
+        .iter()
+        .map(|&tile| if tile == 0 { palette.floor } else { 0 })
+        .collect();
+    let ceiling = walls
+        .iter()
+        .map(|&tile| if tile == 0 { palette.ceiling } else { 0 })
+        .collect();
+    let mut level = Level::new(bitmap.width, bitmap.height, walls, floor, ceiling)?;
+    if let Some(start) = start {
+        level.start = start;
+    }
+    Ok(level)
+}
+
+pub fn load(path: &Path, palette: &Palette) -> Result<Level, Error> {
+    from_bitmap(&BitMap::from_png(path)?, palette, path)
+}
+
+//Whether `path` is a level image rather than a level file
+pub fn is_level_image(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("png")
+}
+
+//Saves the walls layer and the player start of `level` as an image that
+//`load` reads back, floor and ceiling tiles and objects are not saved. Tiles
+//missing from the palette get a made up color, add it to the palette to load
+//the image again.
+pub fn save(level: &Level, palette: &Palette, path: &Path) -> Result<(), Error> {
+    let mut pixels = Vec::with_capacity(level.width * level.height * 3);
+    for y in 0..level.height {
+        for x in 0..level.width {
+            //Open doors are saved closed
+            let tile = level
+                .doors
+                .iter()
+                .find(|door| door.x == x && door.y == y)
+                .map(|door| door.tile)
+                .unwrap_or_else(|| level.get_tile(x as isize, y as isize));
+            let color = if tile == 0
+                && level.start.x.floor() as usize == x
+                && level.start.y.floor() as usize == y
+            {
+                palette.start
+            } else {
+                palette
+                    .color(tile)
+                    .unwrap_or_else(|| palette.fallback_color(tile))
+            };
+            pixels.extend(color);
+        }
+    }
+
+    capture::save_png(path, &pixels, level.width as u32, level.height as u32)
+}
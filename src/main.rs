@@ -1,259 +1,417 @@
 use sdl2::event::Event;
-use sdl2::image::LoadTexture;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::{Color, PixelFormatEnum};
-use sdl2::rect::{Point, Rect};
-use std::time::Instant;
-
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture};
+use std::env;
+use std::error::Error as _;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+mod assets;
 mod bitmap;
-
-const MAP: [u8; 64] = [
-    1, 1, 2, 1, 2, 1, 1, 1,
-    1, 0, 0, 0, 0, 0, 4, 1,
-    1, 0, 3, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 3, 0, 3, 2, 0, 1,
-    1, 0, 1, 0, 0, 2, 0, 1,
-    1, 0, 1, 1, 0, 2, 0, 1,
-    1, 1, 1, 1, 1, 1, 1, 1,
-];
-
-const FLOOR: [u8; 64] = [
-    0, 0, 0, 0, 0, 0, 0, 0,
-    0, 2, 2, 2, 2, 2, 0, 0,
-    0, 2, 0, 2, 2, 2, 2, 0,
-    0, 2, 2, 2, 2, 2, 2, 0,
-    0, 2, 0, 3, 0, 0, 2, 0, 
-    0, 2, 0, 3, 3, 0, 2, 0,
-    0, 2, 0, 0, 3, 0, 2, 0,
-    0, 0, 0, 0, 0, 0, 0, 0,
-];
-
-const CEILING: [u8; 64] = [
-    0, 0, 0, 0, 0, 0, 0, 0,
-    0, 1, 0, 0, 0, 0, 0, 0,
-    0, 1, 0, 0, 0, 0, 0, 0,
-    0, 1, 0, 0, 0, 0, 0, 0,
-    0, 1, 0, 2, 0, 0, 0, 0,
-    0, 1, 0, 2, 2, 0, 0, 0,
-    0, 1, 0, 0, 2, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0,
-];
-
-const MAP_WIDTH: isize = 8;
-const MAP_HEIGHT: isize = 8;
-
-fn get_tile(x: isize, y: isize) -> u8 {
-    if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
-        return 0;
-    }
-
-    MAP[(x + y * MAP_WIDTH) as usize]
+mod capture;
+mod debug;
+mod decal;
+mod editor;
+mod enemy;
+mod error;
+mod event;
+mod font;
+mod framebuffer;
+mod generate;
+mod hud;
+mod level;
+mod levelimage;
+mod minimap;
+mod pack;
+mod pathfind;
+mod player;
+mod projectile;
+mod raycast;
+mod sprite;
+mod tiled;
+mod viewmodel;
+mod weapon;
+mod wolf3d;
+
+use assets::AssetManager;
+use debug::{ColumnInfo, DebugOverlay, Face};
+use decal::{DecalKind, Decals};
+use editor::Editor;
+use enemy::{Attack, Enemy};
+use error::Error;
+use event::{Action, Events};
+use font::Font;
+use framebuffer::FrameBuffer;
+use generate::Algorithm;
+use hud::Hud;
+use level::{Layer, Level};
+use levelimage::Palette;
+use minimap::{Minimap, MinimapMode};
+use pack::Pack;
+use pathfind::{Pathfinder, Rules};
+use player::{Pickup, Player};
+use projectile::{Projectile, Projectiles};
+use raycast::{can_move, raycast};
+use sprite::View;
+use viewmodel::ViewModel;
+use weapon::{Hit, Shot, Target, Weapons};
+
+//Command line options for running the demo
+struct Options {
+    //Extra directories to search for assets, searched before the defaults
+    asset_dirs: Vec<PathBuf>,
+    //Pack files to mount in addition to the default one
+    packs: Vec<PathBuf>,
+    //Level file to play instead of the built in level
+    level: Option<PathBuf>,
+    //Colors of level images, the default palette is used if this is not set
+    palette: Option<PathBuf>,
+    //Generate a level instead of loading one
+    generate: Option<Algorithm>,
+    seed: Option<u64>,
+    size: (usize, usize),
+    //Wolfenstein 3D MAPHEAD and GAMEMAPS files and the map number to import
+    wolf_map: Option<(PathBuf, PathBuf, usize)>,
+    //Recording keeps one frame out of this many
+    record_every: u32,
 }
 
-fn get_ceil(x: isize, y: isize) -> u8 {
-    if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
-        return 0;
-    }
-
-    CEILING[(x + y * MAP_WIDTH) as usize]
+enum Command {
+    Play(Options),
+    //raycast-demo pack [--store] <output> <file or directory>...
+    Pack {
+        output: PathBuf,
+        inputs: Vec<PathBuf>,
+        compress: bool,
+    },
 }
 
-fn get_floor(x: isize, y: isize) -> u8 {
-    if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT {
-        return 0;
-    }
-
-    FLOOR[(x + y * MAP_WIDTH) as usize]
-}
+fn parse_args() -> Result<Command, Error> {
+    let mut args = env::args().skip(1).peekable();
+
+    if args.peek().map(String::as_str) == Some("pack") {
+        args.next();
+        let mut compress = true;
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--store" => compress = false,
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
 
-struct Raycast {
-    x: f64,
-    y: f64,
-    tile_type: u8,
-}
+        if paths.len() < 2 {
+            return Err(Error::InvalidArgument(String::from(
+                "usage: pack [--store] <output> <file or directory>...",
+            )));
+        }
 
-fn dist(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
-    ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt()
-}
+        let output = paths.remove(0);
+        return Ok(Command::Pack {
+            output,
+            inputs: paths,
+            compress,
+        });
+    }
 
-fn raycast(startx: f64, starty: f64, angle: f64, max_dist: f64) -> Raycast {
-    let mut vert = Raycast {
-        x: 0.0,
-        y: 0.0,
-        tile_type: 0,
+    let mut options = Options {
+        asset_dirs: Vec::new(),
+        packs: Vec::new(),
+        level: None,
+        palette: None,
+        generate: None,
+        seed: None,
+        size: (32, 32),
+        wolf_map: None,
+        record_every: 2,
     };
 
-    //Check vertical lines
-    if angle.cos() > 0.0 {
-        let mut rayx = startx.ceil();
-        let mut rayy = (rayx - startx) * angle.tan() + starty;
-        while (startx - rayx).abs() < max_dist {
-            let xind = rayx as isize;
-            let yind = rayy.floor() as isize;
-
-            if xind >= 0
-                && xind < MAP_WIDTH
-                && yind >= 0
-                && yind < MAP_HEIGHT
-                && MAP[(xind + yind * MAP_WIDTH) as usize] != 0
-            {
-                vert = Raycast {
-                    x: rayx,
-                    y: rayy,
-                    tile_type: MAP[(xind + yind * MAP_WIDTH) as usize],
-                };
-
-                break;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--assets" => {
+                let dir = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--assets expects a directory"))
+                })?;
+                options.asset_dirs.push(PathBuf::from(dir));
             }
-
-            rayx += 1.0;
-            rayy += angle.tan();
-        }
-    } else if angle.cos() < 0.0 {
-        let mut rayx = startx.floor();
-        let mut rayy = (rayx - startx) * angle.tan() + starty;
-        while (startx - rayx).abs() < max_dist {
-            let xind = rayx as isize - 1;
-            let yind = rayy.floor() as isize;
-
-            if xind >= 0
-                && xind < MAP_WIDTH
-                && yind >= 0
-                && yind < MAP_HEIGHT
-                && MAP[(xind + yind * MAP_WIDTH) as usize] != 0
-            {
-                vert = Raycast {
-                    x: rayx,
-                    y: rayy,
-                    tile_type: MAP[(xind + yind * MAP_WIDTH) as usize],
+            "--pack" => {
+                let file = args
+                    .next()
+                    .ok_or_else(|| Error::InvalidArgument(String::from("--pack expects a file")))?;
+                options.packs.push(PathBuf::from(file));
+            }
+            "--level" => {
+                let file = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--level expects a file"))
+                })?;
+                options.level = Some(PathBuf::from(file));
+            }
+            "--palette" => {
+                let file = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--palette expects a file"))
+                })?;
+                options.palette = Some(PathBuf::from(file));
+            }
+            "--generate" => {
+                let algorithm = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--generate expects maze, dungeon or cave"))
+                })?;
+                options.generate = Some(algorithm.parse()?);
+            }
+            "--seed" => {
+                let seed = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--seed expects a number"))
+                })?;
+                options.seed = Some(
+                    seed.parse()
+                        .map_err(|_| Error::InvalidArgument(format!("invalid seed: {seed}")))?,
+                );
+            }
+            "--size" => {
+                let size = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--size expects <width>x<height>"))
+                })?;
+                options.size = size
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .ok_or_else(|| Error::InvalidArgument(format!("invalid size: {size}")))?;
+            }
+            "--wolf" => {
+                let usage = || {
+                    Error::InvalidArgument(String::from(
+                        "--wolf expects <maphead> <gamemaps> <map number>",
+                    ))
                 };
-                break;
+                let maphead = args.next().ok_or_else(usage)?;
+                let gamemaps = args.next().ok_or_else(usage)?;
+                let map = args.next().ok_or_else(usage)?;
+                let map = map
+                    .parse()
+                    .map_err(|_| Error::InvalidArgument(format!("invalid map number: {map}")))?;
+                options.wolf_map = Some((PathBuf::from(maphead), PathBuf::from(gamemaps), map));
             }
-
-            rayx -= 1.0;
-            rayy -= angle.tan();
+            "--record-every" => {
+                let every = args.next().ok_or_else(|| {
+                    Error::InvalidArgument(String::from("--record-every expects a number"))
+                })?;
+                options.record_every =
+                    every
+                        .parse()
+                        .ok()
+                        .filter(|&every| every > 0)
+                        .ok_or_else(|| {
+                            Error::InvalidArgument(format!("invalid frame interval: {every}"))
+                        })?;
+            }
+            _ => return Err(Error::InvalidArgument(format!("unknown argument: {arg}"))),
         }
     }
 
-    let mut horiz = Raycast {
-        x: 0.0,
-        y: 0.0,
-        tile_type: 0,
-    };
-
-    //Check horizontal lines
-    if angle.sin() > 0.0 {
-        let mut rayy = starty.ceil();
-        let mut rayx = (rayy - starty) * 1.0 / angle.tan() + startx;
-        while (starty - rayy).abs() < max_dist {
-            let xind = rayx.floor() as isize;
-            let yind = rayy as isize;
-
-            if xind >= 0
-                && xind < MAP_WIDTH
-                && yind >= 0
-                && yind < MAP_HEIGHT
-                && MAP[(xind + yind * MAP_WIDTH) as usize] != 0
-            {
-                horiz = Raycast {
-                    x: rayx,
-                    y: rayy,
-                    tile_type: MAP[(xind + yind * MAP_WIDTH) as usize],
-                };
-                break;
-            }
+    Ok(Command::Play(options))
+}
 
-            rayy += 1.0;
-            rayx += 1.0 / angle.tan();
-        }
-    } else if angle.sin() < 0.0 {
-        let mut rayy = starty.floor();
-        let mut rayx = (rayy - starty) * 1.0 / angle.tan() + startx;
-        while (starty - rayy).abs() < max_dist {
-            let xind = rayx.floor() as isize;
-            let yind = rayy as isize - 1;
-
-            if xind >= 0
-                && xind < MAP_WIDTH
-                && yind >= 0
-                && yind < MAP_HEIGHT
-                && MAP[(xind + yind * MAP_WIDTH) as usize] != 0
-            {
-                horiz = Raycast {
-                    x: rayx,
-                    y: rayy,
-                    tile_type: MAP[(xind + yind * MAP_WIDTH) as usize],
-                };
-                break;
-            }
+fn pack_files(output: &Path, inputs: &[PathBuf], compress: bool) -> Result<(), Error> {
+    let files = pack::collect_files(inputs)?;
+    pack::write_pack(output, &files, compress)?;
 
-            rayy -= 1.0;
-            rayx -= 1.0 / angle.tan();
-        }
+    let pack = Pack::open(output)?;
+    for name in pack.names() {
+        println!("{name}");
     }
+    println!("packed {} files into {}", files.len(), output.display());
+    Ok(())
+}
 
-    //Return the value that is closest
-    if (dist(horiz.x, horiz.y, startx, starty) < dist(vert.x, vert.y, startx, starty)
-        && horiz.tile_type != 0)
-        || vert.tile_type == 0
-    {
-        return horiz;
-    } else {
-        return vert;
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {err}");
+        let mut source = err.source();
+        while let Some(cause) = source {
+            eprintln!("  caused by: {cause}");
+            source = cause.source();
+        }
+        process::exit(1);
     }
 }
 
-fn main() -> Result<(), String> {
-    let ctx = sdl2::init().unwrap();
-    let vid_subsystem = ctx.video().unwrap();
+fn run() -> Result<(), Error> {
+    let options = match parse_args()? {
+        Command::Play(options) => options,
+        Command::Pack {
+            output,
+            inputs,
+            compress,
+        } => return pack_files(&output, &inputs, compress),
+    };
+
+    let ctx = sdl2::init().map_err(Error::sdl_init("SDL"))?;
+    let vid_subsystem = ctx.video().map_err(Error::sdl_init("video subsystem"))?;
 
     let window = vid_subsystem
         .window("Raycast Demo", 800, 600)
         .position_centered()
         .build()
-        .unwrap();
+        .map_err(Error::sdl_init("window"))?;
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let mut canvas = window
+        .into_canvas()
+        .present_vsync()
+        .build()
+        .map_err(Error::sdl_init("renderer"))?;
+    //Lighting darkens walls by drawing translucent black over them
+    canvas.set_blend_mode(BlendMode::Blend);
 
     let texture_creator = canvas.texture_creator();
-    let texture = texture_creator
-        .load_texture("assets/textures.png")
-        .map_err(|e| e.to_string())?;
-    let texture_pixels =
-        bitmap::BitMap::from_png("assets/textures.png").map_err(|e| e.to_string())?;
-    let mut texture_shaded = texture_creator
-        .load_texture("assets/textures.png")
-        .map_err(|e| e.to_string())?;
-    texture_shaded.set_color_mod(255 / 8 * 5, 255 / 8 * 5, 255 / 8 * 5);
-    let texture_shaded = texture_shaded; //Remove mutability
-
-    let sprite = texture_creator
-        .load_texture("assets/sprite.png")
-        .map_err(|e| e.to_string())?;
-
-    let spritex = 1.5f64;
-    let spritey = 1.5f64;
-
-    let mut floor_texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::BGRA8888, 200, 75)
-        .unwrap();
-
-    let mut ceil_texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::BGRA8888, 200, 75)
-        .unwrap();
-
-    let mut event_pump = ctx.event_pump().unwrap();
-
-    let mut camx = 3.5;
-    let mut camy = 3.5;
-    let mut cam_rotation = 0.0f64;
+    let mut assets = AssetManager::new(
+        &texture_creator,
+        assets::default_search_paths(&options.asset_dirs),
+    );
+    for path in options.packs.iter().chain(assets::default_packs().iter()) {
+        assets.mount(Pack::open(path)?);
+    }
+
+    if let Some(path) = &options.palette {
+        assets.palette = Palette::load(path)?;
+    }
+
+    let mut level = if let Some(algorithm) = options.generate {
+        //Without a seed pick one from the clock and print it so that a level
+        //worth keeping can be generated again
+        let seed = options.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+        let (width, height) = options.size;
+        println!("generating {width}x{height} level with seed {seed}");
+        generate::generate(algorithm, width, height, seed)?
+    } else if let Some((maphead, gamemaps, map)) = &options.wolf_map {
+        wolf3d::load(maphead, gamemaps, *map)?
+    } else if let Some(path) = &options.level {
+        assets.load_level(path)?
+    } else {
+        Level::default_level()?
+    };
+    //The level as loaded or edited, before anything was opened, pushed or
+    //changed by playing it. Starting over goes back to this.
+    let mut loaded_level = level.clone();
+
+    let mut texture_pixels = assets.bitmap("textures.png")?;
+    let mut enemy_pixels = assets.bitmap("enemy.png")?;
+    let mut font = Font::new(assets.bitmap("font.png")?, 6, 8);
+
+    assets.load_texture("textures.png")?;
+    assets.load_texture_tinted(
+        "textures_shaded",
+        "textures.png",
+        Color::RGB(255 / 8 * 5, 255 / 8 * 5, 255 / 8 * 5),
+    )?;
+    assets.load_texture("sprite.png")?;
+    assets.load_texture("enemy.png")?;
+    assets.load_texture("weapon.png")?;
+    assets.load_texture("projectile.png")?;
+    assets.load_texture("decals.png")?;
+    assets.load_texture("items.png")?;
+    assets.load_texture("doors.png")?;
+    assets.load_texture_tinted(
+        "doors_shaded",
+        "doors.png",
+        Color::RGB(255 / 8 * 5, 255 / 8 * 5, 255 / 8 * 5),
+    )?;
+    assets.load_texture("switches.png")?;
+    assets.load_texture_tinted(
+        "switches_shaded",
+        "switches.png",
+        Color::RGB(255 / 8 * 5, 255 / 8 * 5, 255 / 8 * 5),
+    )?;
+
+    //Floor and ceiling are drawn into a single texture so that the horizon
+    //can move up and down when the camera pitches
+    let mut plane_texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::BGRA8888, 200, 150)
+        .map_err(Error::sdl_init("texture"))?;
+
+    let mut minimap = Minimap::new(level.width, level.height);
+    //The editor saves back to the level file it was loaded from, other kinds
+    //of levels are saved to a new level file
+    let save_path = assets
+        .level_path()
+        .filter(|path| !tiled::is_tiled_map(path) && !levelimage::is_level_image(path))
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("edited.lvl"));
+    let mut editor = Editor::new(save_path, editor::tile_count(&texture_pixels));
+    let mut enemies = enemy::spawn(&level);
+    let mut pathfinder = Pathfinder::new(Rules {
+        diagonal: true,
+        through_doors: false,
+    });
+    //Red tint shown after the player is hurt, fades out over time
+    let mut hurt_flash = 0.0f64;
+    let mut player = Player::new();
+    let mut pickups = player::spawn_pickups(&level);
+    //Gold tint shown after picking something up
+    let mut pickup_flash = 0.0f64;
+    let mut weapons = Weapons::new();
+    let mut view_model = ViewModel::new();
+    //Where shots hit walls, shown on the minimap for a moment: position and
+    //seconds left
+    let mut impacts: Vec<(f64, f64, f64)> = Vec::new();
+    let mut projectiles = Projectiles::new();
+    let mut decals = Decals::new();
+    let mut events = Events::new();
+    //Decorations spawned by events, gone when the level starts over
+    let mut spawned: Vec<(f64, f64)> = Vec::new();
+    let mut minimap_texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::BGRA8888, 800, 600)
+        .map_err(Error::sdl_init("texture"))?;
+    minimap_texture.set_blend_mode(BlendMode::Blend);
+
+    let mut hud = Hud::new();
+    let mut hud_texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::BGRA8888, 800, 600)
+        .map_err(Error::sdl_init("texture"))?;
+    hud_texture.set_blend_mode(BlendMode::Blend);
+
+    let mut event_pump = ctx.event_pump().map_err(Error::sdl_init("event pump"))?;
+
+    let mut camx = level.start.x;
+    let mut camy = level.start.y;
+    let mut cam_rotation = level.start.angle;
     let mut dt = 0.0;
     let mut speed = 0.0;
     let mut rotation_speed = 0.0;
-    const FOV: f64 = 3.14159 / 12.0 * 5.0;
+    const FOV: f64 = PI / 12.0 * 5.0;
+
+    //Vertical look: the horizon is shifted by `cam_pitch` pixels from the
+    //center of the screen (positive values look up)
+    let mut cam_pitch = 0.0f64;
+    let mut pitch_speed = 0.0;
+    const MAX_PITCH: f64 = 200.0;
+
+    //Height of the camera above the floor, 0.0 is the floor and 1.0 is the
+    //ceiling. `eye_height` eases towards the standing or crouching height and
+    //`jump_height` is added on top of it while in the air
+    const STAND_HEIGHT: f64 = 0.5;
+    const CROUCH_HEIGHT: f64 = 0.3;
+    const JUMP_SPEED: f64 = 2.0;
+    const GRAVITY: f64 = 8.0;
+    let mut eye_height = STAND_HEIGHT;
+    let mut jump_height = 0.0f64;
+    let mut jump_velocity = 0.0f64;
+    let mut crouching = false;
 
     let mut depthbuffer = [9999.0f64; 200];
+    let mut columns = [ColumnInfo::default(); 200];
+    let mut debug_overlay = DebugOverlay::new();
+
+    let mut take_screenshot = false;
+    let mut recorder = capture::Recorder::new(options.record_every);
     'running: loop {
         let start = Instant::now();
 
@@ -263,6 +421,76 @@ fn main() -> Result<(), String> {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => {
+                    editor.toggle(&mut minimap);
+                    //Edit the level as it was loaded and start playing the
+                    //edited level over, enemies from where it puts them
+                    if editor.active {
+                        level = loaded_level.clone();
+                        decals.clear();
+                    } else {
+                        loaded_level = level.clone();
+                        enemies = enemy::spawn(&level);
+                        pickups = player::spawn_pickups(&level);
+                        projectiles.clear();
+                        events.clear();
+                        spawned.clear();
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } if editor.active => editor.next_tool(),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if editor.active && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    match keycode {
+                        Keycode::Z => editor.undo(&mut level),
+                        Keycode::Y => editor.redo(&mut level),
+                        Keycode::S => {
+                            editor.save(&level);
+                            assets.level_written(&editor.save_path);
+                        }
+                        _ => {}
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if editor.active && editor::digit(keycode).is_some() => {
+                    editor.select(editor::digit(keycode).unwrap_or(0));
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if editor::digit(keycode).is_some_and(|digit| digit > 0) => {
+                    weapons.select(editor::digit(keycode).unwrap_or(1) as usize - 1);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } if editor.active => {
+                    if let Some((worldx, worldy)) = minimap.screen_to_world(x, y) {
+                        editor.begin_stroke(&level);
+                        editor.paint(&mut level, worldx, worldy, mouse_btn == MouseButton::Right);
+                    }
+                }
+                Event::MouseMotion {
+                    mousestate, x, y, ..
+                } if editor.active => {
+                    let world = minimap.screen_to_world(x, y);
+                    editor.cursor = world
+                        .map(|(worldx, worldy)| (worldx.floor() as isize, worldy.floor() as isize));
+                    if let Some((worldx, worldy)) = world {
+                        editor.paint(&mut level, worldx, worldy, mousestate.right());
+                    }
+                }
+                Event::MouseButtonUp { .. } => editor.end_stroke(),
                 Event::KeyDown {
                     keycode: Some(Keycode::Left),
                     ..
@@ -311,269 +539,720 @@ fn main() -> Result<(), String> {
                 } => {
                     speed = 0.0;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageUp),
+                    ..
+                } => {
+                    pitch_speed = 300.0;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageDown),
+                    ..
+                } => {
+                    pitch_speed = -300.0;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::PageUp | Keycode::PageDown),
+                    ..
+                } => {
+                    pitch_speed = 0.0;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home),
+                    ..
+                } => {
+                    cam_pitch = 0.0;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    repeat: false,
+                    ..
+                } if jump_height == 0.0 => {
+                    //Only jump when standing on the floor
+                    jump_velocity = JUMP_SPEED;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    repeat: false,
+                    ..
+                } => {
+                    hud.visible = !hud.visible;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    repeat: false,
+                    ..
+                } => {
+                    debug_overlay.enabled = !debug_overlay.enabled;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    repeat: false,
+                    ..
+                } => {
+                    debug_overlay.toggle_freeze();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    debug_overlay.request_step();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Comma),
+                    ..
+                } => {
+                    debug_overlay.move_cursor(-1, columns.len());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    ..
+                } => {
+                    debug_overlay.move_cursor(1, columns.len());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => match capture::level_image(&level, &assets.palette) {
+                    Ok(path) => println!("saved level image to {}", path.display()),
+                    Err(msg) => eprintln!("failed to save level image: {msg}"),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => {
+                    take_screenshot = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some((dir, count)) = recorder.stop() {
+                        println!("saved {count} frames to {}", dir.display());
+                    } else if let Err(msg) = recorder.start() {
+                        eprintln!("failed to start recording: {msg}");
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    minimap.mode = minimap.mode.next();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    repeat: false,
+                    ..
+                } => {
+                    minimap.follow_rotation = !minimap.follow_rotation;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    repeat: false,
+                    ..
+                } => {
+                    //Open or close the door in front of the camera, but never
+                    //close a door on top of the camera
+                    let doorx = (camx + cam_rotation.cos()).floor() as isize;
+                    let doory = (camy + cam_rotation.sin()).floor() as isize;
+                    //The key is only needed to open a locked door
+                    let lock = level
+                        .door_at(doorx, doory)
+                        .filter(|door| !door.open)
+                        .and_then(|door| door.lock)
+                        .filter(|key| !player.has_item(key.item()));
+                    if let Some(key) = lock {
+                        hud.show_message(format!(
+                            "LOCKED - NEEDS THE {} KEY",
+                            key.name().to_uppercase()
+                        ));
+                    } else if let Some(event) = level.use_switch(doorx, doory) {
+                        events.fire(&event);
+                    } else if (doorx, doory) != (camx.floor() as isize, camy.floor() as isize)
+                        && !level.toggle_door(doorx, doory)
+                    {
+                        //Push walls slide away along the axis the camera
+                        //faces the most
+                        let (sin, cos) = cam_rotation.sin_cos();
+                        let dir = if cos.abs() > sin.abs() {
+                            (cos.signum() as isize, 0)
+                        } else {
+                            (0, sin.signum() as isize)
+                        };
+                        //The wall stops short of anything in its way
+                        let occupants: Vec<(f64, f64)> = [(camx, camy)]
+                            .into_iter()
+                            .chain(enemies.iter().map(|enemy| (enemy.x, enemy.y)))
+                            .chain(pickups.iter().map(|pickup| (pickup.x, pickup.y)))
+                            .chain(spawned.iter().copied())
+                            .collect();
+                        level.push_wall(doorx, doory, dir, &occupants);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => {
+                    crouching = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::LCtrl | Keycode::RCtrl),
+                    ..
+                } => {
+                    weapons.set_trigger(true);
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::LCtrl | Keycode::RCtrl),
+                    ..
+                } => {
+                    weapons.set_trigger(false);
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => {
+                    crouching = false;
+                }
                 _ => {}
             }
         }
 
-        cam_rotation += dt * rotation_speed;
-        while cam_rotation > 3.14159 * 2.0 {
-            cam_rotation -= 3.14159 * 2.0
+        //Pick up any assets or level edits saved since the last frame, the
+        //camera stays where it is
+        let reloaded = assets.reload_changed();
+        if !reloaded.bitmaps.is_empty() {
+            texture_pixels = assets.bitmap("textures.png")?;
+            editor.tile_count = editor::tile_count(&texture_pixels);
+            enemy_pixels = assets.bitmap("enemy.png")?;
+            font = Font::new(assets.bitmap("font.png")?, 6, 8);
+        }
+        if let Some(new_level) = reloaded.level {
+            minimap.resize(new_level.width, new_level.height);
+            enemies = enemy::spawn(&new_level);
+            pickups = player::spawn_pickups(&new_level);
+            projectiles.clear();
+            decals.clear();
+            events.clear();
+            spawned.clear();
+            loaded_level = new_level.clone();
+            level = new_level;
+        }
+
+        let texture = assets.texture("textures.png")?;
+        let texture_shaded = assets.texture("textures_shaded")?;
+        let sprite = assets.texture("sprite.png")?;
+        let enemy_texture = assets.texture("enemy.png")?;
+        let weapon_texture = assets.texture("weapon.png")?;
+        let projectile_texture = assets.texture("projectile.png")?;
+        let decal_texture = assets.texture("decals.png")?;
+        let items_texture = assets.texture("items.png")?;
+        let door_texture = assets.texture("doors.png")?;
+        let door_texture_shaded = assets.texture("doors_shaded")?;
+        let switch_texture = assets.texture("switches.png")?;
+        let switch_texture_shaded = assets.texture("switches_shaded")?;
+
+        //Time step for moving the camera, this is zero while the debug
+        //overlay has frozen the frame
+        let step_dt = debug_overlay.step_dt(dt);
+
+        cam_rotation += step_dt * rotation_speed;
+        while cam_rotation > PI * 2.0 {
+            cam_rotation -= PI * 2.0
         }
         while cam_rotation <= 0.0 {
-            cam_rotation += 3.14159 * 2.0
+            cam_rotation += PI * 2.0
         }
 
-        if (speed > 0.0 && raycast(camx, camy, cam_rotation, dt * speed).tile_type == 0)
-            || (speed < 0.0
-                && raycast(camx, camy, cam_rotation + 3.14159, dt * -speed).tile_type == 0)
-        {
-            camx += cam_rotation.cos() * dt * speed;
-            camy += cam_rotation.sin() * dt * speed;
+        if can_move(&level, camx, camy, cam_rotation, step_dt * speed) {
+            camx += cam_rotation.cos() * step_dt * speed;
+            camy += cam_rotation.sin() * step_dt * speed;
         }
 
-        floor_texture
-            .with_lock(None, |pixels: &mut [u8], pitch: usize| {
-                let height = pixels.len() / pitch;
-
-                for y in 0..height {
-                    let dist = (height as f64) / y as f64;
-
-                    for x in 0..pitch / 4 {
-                        let angle = x as f64 / (pitch as f64 / 4.0) * FOV - FOV / 2.0;
-                        let posx = dist * angle.tan();
-                        let posy = dist;
-
-                        let floorx =
-                            posx * (-cam_rotation).sin() + posy * (-cam_rotation).cos() + camx;
-                        let floory =
-                            posx * (-cam_rotation).cos() - posy * (-cam_rotation).sin() + camy;
-
-                        let tile_type = get_tile(floorx.floor() as isize, floory.floor() as isize);
-                        if tile_type != 0 {
-                            texture_pixels.sample(
-                                floorx.fract().abs() / 4.0 + 0.25 * tile_type as f64 - 0.25,
-                                0.99,
-                                &mut pixels[(y * pitch + x * 4 + 1)..(y * pitch + x * 4 + 4)],
-                            );
-                            if floory.fract() > 0.9 || floory.fract() < 0.1 {
-                                pixels[y * pitch + x * 4 + 1] /= 8;
-                                pixels[y * pitch + x * 4 + 2] /= 8;
-                                pixels[y * pitch + x * 4 + 3] /= 8;
-
-                                pixels[y * pitch + x * 4 + 1] *= 5;
-                                pixels[y * pitch + x * 4 + 2] *= 5;
-                                pixels[y * pitch + x * 4 + 3] *= 5;
-                            }
+        //Enemies, push walls and events stand still while the level is being
+        //edited
+        if !editor.active {
+            level.update_pushwalls(step_dt);
+            events.update_triggers(&level, camx, camy);
+            let mut level_complete = false;
+            for action in events.dispatch(&mut level) {
+                match action {
+                    Action::Spawn(sprite) if sprite.kind == "enemy" => {
+                        enemies.push(Enemy::new(sprite.x, sprite.y));
+                    }
+                    Action::Spawn(sprite) if player::pickup_frame(&sprite.kind).is_some() => {
+                        pickups.push(Pickup {
+                            kind: sprite.kind,
+                            x: sprite.x,
+                            y: sprite.y,
+                        });
+                    }
+                    Action::Spawn(sprite) => spawned.push((sprite.x, sprite.y)),
+                    Action::Teleport(to) => {
+                        camx = to.x;
+                        camy = to.y;
+                        cam_rotation = to.angle;
+                    }
+                    Action::EndLevel => level_complete = true,
+                    Action::OpenDoor { .. } | Action::SetTile { .. } => {}
+                }
+            }
 
-                            continue;
-                        }
+            let mut damage = 0;
+            for enemy in &mut enemies {
+                match enemy.update(&level, &mut pathfinder, step_dt, camx, camy) {
+                    Some(Attack::Melee(melee)) => damage += melee,
+                    Some(Attack::Ranged(projectile)) => projectiles.fire(projectile),
+                    None => {}
+                }
+            }
+            damage += projectiles.update(&level, step_dt, (camx, camy), &mut enemies, &mut decals);
+            hurt_flash = (hurt_flash + damage as f64 * 0.05).min(0.6);
+            player.hurt(damage);
 
-                        let tile_type = get_floor(floorx.floor() as isize, floory.floor() as isize);
-                        texture_pixels.sample(
-                            floorx.fract().abs() / 4.0 + 0.25 * (tile_type as f64 - 1.0),
-                            floory.fract().abs(),
-                            &mut pixels[(y * pitch + x * 4 + 1)..(y * pitch + x * 4 + 4)],
-                        );
+            //Start the level over
+            if level_complete {
+                hud.show_message(String::from("LEVEL COMPLETE"));
+            }
+            if player.is_dead() || level_complete {
+                level = loaded_level.clone();
+                camx = level.start.x;
+                camy = level.start.y;
+                cam_rotation = level.start.angle;
+                player = Player::new();
+                enemies = enemy::spawn(&level);
+                pickups = player::spawn_pickups(&level);
+                projectiles.clear();
+                decals.clear();
+                events.clear();
+                spawned.clear();
+            }
 
-                        pixels[y * pitch + x * 4 + 1] /= 2;
-                        pixels[y * pitch + x * 4 + 2] /= 2;
-                        pixels[y * pitch + x * 4 + 3] /= 2;
+            if player::collect(&mut pickups, &mut player, camx, camy) {
+                pickup_flash = 0.3;
+            }
+        }
+        hurt_flash = (hurt_flash - step_dt).max(0.0);
+        pickup_flash = (pickup_flash - step_dt).max(0.0);
+
+        cam_pitch = (cam_pitch + pitch_speed * step_dt).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let target_height = if crouching {
+            CROUCH_HEIGHT
+        } else {
+            STAND_HEIGHT
+        };
+        eye_height += (target_height - eye_height) * (step_dt * 10.0).min(1.0);
+
+        if jump_height > 0.0 || jump_velocity > 0.0 {
+            jump_velocity -= GRAVITY * step_dt;
+            jump_height += jump_velocity * step_dt;
+            if jump_height <= 0.0 {
+                jump_height = 0.0;
+                jump_velocity = 0.0;
+            }
+        }
+
+        //Keep the camera strictly between the floor and the ceiling
+        let cam_z = (eye_height + jump_height).clamp(0.05, 0.95);
+        let horizon = 300.0 + cam_pitch;
+
+        //Shots go through the center of the screen
+        let ammo = weapons.weapon().ammo;
+        if !editor.active && weapons.update(step_dt, player.ammo(ammo) > 0) {
+            player.use_ammo(ammo);
+            view_model.fire(weapons.weapon().reload);
+            if let Some(kind) = weapons.weapon().projectile {
+                projectiles.fire(Projectile::new(kind, camx, camy, cam_rotation, true));
+            } else {
+                let living: Vec<usize> = (0..enemies.len())
+                    .filter(|&i| !enemies[i].is_dead())
+                    .collect();
+                let targets: Vec<Target> = living
+                    .iter()
+                    .map(|&i| Target {
+                        x: enemies[i].x,
+                        y: enemies[i].y,
+                        sheet: &enemy_pixels,
+                        frame: enemies[i].frame(),
+                    })
+                    .collect();
+                let shot = Shot {
+                    x: camx,
+                    y: camy,
+                    z: cam_z,
+                    angle: cam_rotation,
+                    slope: cam_pitch / 600.0,
+                    range: weapons.weapon().range,
+                };
+                match weapon::hitscan(&level, &targets, &shot, FOV) {
+                    Hit::Target { index, .. } => {
+                        enemies[living[index]].hurt(weapons.weapon().damage)
+                    }
+                    Hit::Wall { ray, z } => {
+                        impacts.push((ray.x, ray.y, 1.0));
+                        decals.add(&ray, z, cam_rotation, DecalKind::BulletHole);
                     }
+                    Hit::Nothing => {}
                 }
-            })
-            .unwrap();
+            }
+        }
+        for impact in &mut impacts {
+            impact.2 -= step_dt;
+        }
+        impacts.retain(|impact| impact.2 > 0.0);
+        view_model.update(step_dt, speed);
+
+        let drawn = plane_texture.with_lock(None, |pixels: &mut [u8], pitch: usize| {
+            let height = pixels.len() / pitch;
+
+            for y in 0..height {
+                //Distance from the horizon to the center of this row in
+                //screen pixels, rows below the horizon show the floor
+                let offset = (y * 4 + 2) as f64 - horizon;
+                let is_floor = offset > 0.0;
+                let dist = if is_floor {
+                    cam_z * 600.0 / offset
+                } else {
+                    (1.0 - cam_z) * 600.0 / -offset
+                };
 
-        canvas
-            .copy(&floor_texture, None, Rect::new(0, 300, 800, 300))
-            .unwrap();
-
-        ceil_texture
-            .with_lock(None, |pixels: &mut [u8], pitch: usize| {
-                let height = pixels.len() / pitch;
-
-                for y in 0..height {
-                    let dist = (height as f64) / (height - y) as f64;
-
-                    for x in 0..pitch / 4 {
-                        let angle = x as f64 / (pitch as f64 / 4.0) * FOV - FOV / 2.0;
-                        let posx = dist * angle.tan();
-                        let posy = dist;
-
-                        let ceilx =
-                            posx * (-cam_rotation).sin() + posy * (-cam_rotation).cos() + camx;
-                        let ceily =
-                            posx * (-cam_rotation).cos() - posy * (-cam_rotation).sin() + camy;
-
-                        let tile_type = get_tile(ceilx.floor() as isize, ceily.floor() as isize);
-                        if tile_type != 0 {
-                            texture_pixels.sample(
-                                ceilx.fract().abs() / 4.0 + 0.25 * tile_type as f64 - 0.25,
-                                0.0,
-                                &mut pixels[(y * pitch + x * 4 + 1)..(y * pitch + x * 4 + 4)],
-                            );
-
-                            if ceily.fract() > 0.9 || ceily.fract() < 0.1 {
-                                pixels[y * pitch + x * 4 + 1] /= 8;
-                                pixels[y * pitch + x * 4 + 2] /= 8;
-                                pixels[y * pitch + x * 4 + 3] /= 8;
-
-                                pixels[y * pitch + x * 4 + 1] *= 5;
-                                pixels[y * pitch + x * 4 + 2] *= 5;
-                                pixels[y * pitch + x * 4 + 3] *= 5;
-                            }
+                for x in 0..pitch / 4 {
+                    let pixel = &mut pixels[(y * pitch + x * 4 + 1)..(y * pitch + x * 4 + 4)];
 
-                            continue;
-                        }
+                    if !dist.is_finite() {
+                        pixel.fill(0);
+                        continue;
+                    }
+
+                    let angle = x as f64 / (pitch as f64 / 4.0) * FOV - FOV / 2.0;
+                    let posx = dist * angle.tan();
+                    let posy = dist;
 
-                        let tile_type = get_ceil(ceilx.floor() as isize, ceily.floor() as isize);
+                    let planex = posx * (-cam_rotation).sin() + posy * (-cam_rotation).cos() + camx;
+                    let planey = posx * (-cam_rotation).cos() - posy * (-cam_rotation).sin() + camy;
+
+                    let tile_type =
+                        level.get_tile(planex.floor() as isize, planey.floor() as isize);
+                    if tile_type != 0 {
                         texture_pixels.sample(
-                            ceilx.fract().abs() / 4.0 + 0.25 * (tile_type as f64 - 1.0),
-                            ceily.fract().abs(),
-                            &mut pixels[(y * pitch + x * 4 + 1)..(y * pitch + x * 4 + 4)],
+                            planex.fract().abs() / 4.0 + 0.25 * tile_type as f64 - 0.25,
+                            if is_floor { 0.99 } else { 0.0 },
+                            pixel,
                         );
 
-                        pixels[y * pitch + x * 4 + 1] /= 2;
-                        pixels[y * pitch + x * 4 + 2] /= 2;
-                        pixels[y * pitch + x * 4 + 3] /= 2;
+                        if planey.fract() > 0.9 || planey.fract() < 0.1 {
+                            for channel in pixel.iter_mut() {
+                                *channel = *channel / 8 * 5;
+                            }
+                        }
+
+                        let light = level.light_at(planex, planey);
+                        for channel in pixel.iter_mut() {
+                            *channel = (*channel as f64 * light) as u8;
+                        }
+
+                        continue;
+                    }
+
+                    let tile_type = if is_floor {
+                        level.get_floor(planex.floor() as isize, planey.floor() as isize)
+                    } else {
+                        level.get_ceil(planex.floor() as isize, planey.floor() as isize)
+                    };
+                    texture_pixels.sample(
+                        planex.fract().abs() / 4.0 + 0.25 * (tile_type as f64 - 1.0),
+                        planey.fract().abs(),
+                        pixel,
+                    );
+
+                    let light = level.light_at(planex, planey) / 2.0;
+                    for channel in pixel.iter_mut() {
+                        *channel = (*channel as f64 * light) as u8;
                     }
                 }
-            })
-            .unwrap();
+            }
+        });
+        drawn.map_err(Error::Sdl)?;
 
         canvas
-            .copy(&ceil_texture, None, Rect::new(0, 0, 800, 300))
-            .unwrap();
+            .copy(&plane_texture, None, Rect::new(0, 0, 800, 600))
+            .map_err(Error::Sdl)?;
 
         let mut angle = cam_rotation - FOV / 2.0;
         for i in 0..200 {
-            let ray = raycast(camx, camy, angle, 64.0);
+            let ray = raycast(&level, camx, camy, angle, 64.0);
+            columns[i as usize] = ColumnInfo::default();
+            depthbuffer[i as usize] = 9999.0;
 
             if ray.tile_type != 0 {
                 let d =
                     (ray.x - camx) * (cam_rotation).cos() + (ray.y - camy) * (cam_rotation).sin();
                 depthbuffer[i as usize] = d;
-                let pixel_pos;
-                if ray.x.floor() == ray.x {
-                    pixel_pos = (16.0 * ray.y.fract()) as i32 + 16 * (ray.tile_type as i32 - 1);
+                columns[i as usize] = ColumnInfo {
+                    depth: d,
+                    face: if ray.vertical {
+                        Face::Vertical
+                    } else {
+                        Face::Horizontal
+                    },
+                    tile_type: ray.tile_type,
+                };
+                minimap.reveal_ray(camx, camy, ray.x, ray.y);
+                //The wall spans from the floor to the ceiling, the part
+                //above the horizon depends on how high the camera is
+                let wall_height = ((1.0 / d) * 150.0).ceil() * 4.0;
+                let wall_rect = Rect::new(
+                    i * 4,
+                    (horizon - wall_height * (1.0 - cam_z)) as i32,
+                    4,
+                    wall_height as u32,
+                );
+                //Locked doors show the color of their key and switches
+                //whether they are on instead of the wall texture
+                let (cellx, celly, _, _) = decal::face(&ray, angle);
+                let lock = level.door_at(cellx, celly).and_then(|door| door.lock);
+                let (texture, texture_shaded, tile) = if let Some(key) = lock {
+                    (door_texture, door_texture_shaded, key as i32)
+                } else if let Some(switch) = level.switch_at(cellx, celly) {
+                    (switch_texture, switch_texture_shaded, switch.on as i32)
+                } else {
+                    (texture, texture_shaded, ray.tile_type as i32 - 1)
+                };
+                let pixel_pos = (16.0 * ray.u) as i32 + 16 * tile;
+                if ray.vertical {
                     canvas
-                        .copy(
-                            &texture,
-                            Rect::new(pixel_pos, 0, 1, 16),
-                            Rect::from_center(
-                                Point::new(i * 4 + 2, 300),
-                                4,
-                                (((1.0 / d) * 150.0).ceil() * 4.0) as u32,
-                            ),
-                        )
-                        .unwrap();
+                        .copy(texture, Rect::new(pixel_pos, 0, 1, 16), wall_rect)
+                        .map_err(Error::Sdl)?;
                 } else {
-                    pixel_pos = (16.0 * ray.x.fract()) as i32 + 16 * (ray.tile_type as i32 - 1);
                     canvas
-                        .copy(
-                            &texture_shaded,
-                            Rect::new(pixel_pos, 0, 1, 16),
-                            Rect::from_center(
-                                Point::new(i * 4 + 2, 300),
-                                4,
-                                (((1.0 / d) * 150.0).ceil() * 4.0) as u32,
-                            ),
-                        )
-                        .unwrap();
+                        .copy(texture_shaded, Rect::new(pixel_pos, 0, 1, 16), wall_rect)
+                        .map_err(Error::Sdl)?;
+                }
+                decals.draw_column(&mut canvas, decal_texture, &ray, angle, wall_rect)?;
+
+                //Darken the column by drawing black over it
+                let light = level.light_at(ray.x, ray.y);
+                if light < 1.0 {
+                    canvas.set_draw_color(Color::RGBA(0, 0, 0, ((1.0 - light) * 255.0) as u8));
+                    canvas.fill_rect(wall_rect).map_err(Error::Sdl)?;
                 }
             }
 
             angle += FOV * 1.0 / 200.0;
         }
 
-        //Draw the sprite
-        {
-            let sprite_trans_x = spritex - camx;
-            let sprite_trans_y = spritey - camy;
-            let sprite_rotated_y =
-                sprite_trans_x * (-cam_rotation).cos() - sprite_trans_y * (-cam_rotation).sin();
-            let sprite_rotated_x =
-                sprite_trans_x * (-cam_rotation).sin() + sprite_trans_y * (-cam_rotation).cos();
-
-			let sprite_sz = 400.0;
-
-            let sprite_screen_size = (sprite_sz / sprite_rotated_y) as u32;
-            let sprite_screen_y =
-                (300.0 / sprite_rotated_y + 300.0 - sprite_screen_size as f64 / 2.0) as i32;
-            let norm_x = (sprite_rotated_x / sprite_rotated_y).atan() / FOV + 0.5;
-			let sprite_screen_x = (norm_x * 800.0) as i32;
-
-			let fov_range = 2.0 * (FOV / 2.0).tan() * sprite_rotated_y;
-			let sprite_start_x = ((sprite_rotated_x - sprite_sz / 600.0) / fov_range) + 0.5;
-			let sprite_end_x = ((sprite_rotated_x + sprite_sz / 600.0) / fov_range) + 0.5;
-
-			if ((sprite_start_x < 1.0 && sprite_end_x > 0.0) || (sprite_end_x > 1.0 && sprite_start_x < 0.0)) 
-				&& sprite_rotated_y > 0.0 {
-				let startx = (sprite_screen_x - sprite_screen_size as i32 / 2) / 4;
-            	let endx = (sprite_screen_x + sprite_screen_size as i32 / 2) / 4;
-            	let mut pixel_x = 0.0f64;
-            	for i in startx..endx {
-            	    if i >= 0
-            	        && (i as usize) < depthbuffer.len()
-            	        && depthbuffer[i as usize] > sprite_rotated_y
-            	    {
-            	        canvas
-            	            .copy(
-            	                &sprite,
-            	                Rect::new(pixel_x as i32, 0, 1, 64),
-            	                Rect::from_center(
-            	                    Point::new(i * 4 + 2, sprite_screen_y),
-            	                    4,
-            	                    sprite_screen_size,
-            	                ),
-            	            )
-            	            .unwrap();
-            	    }
-            	    pixel_x += 64.0 / sprite_screen_size as f64 * 4.0;
-            	}
-			}
+        //Draw the sprites from the furthest to the nearest so that nearer
+        //sprites cover the ones behind them
+        let view = View {
+            x: camx,
+            y: camy,
+            rotation: cam_rotation,
+            z: cam_z,
+            horizon,
+            fov: FOV,
+        };
+        //While editing, enemies are drawn where the level places them
+        let mut sprites: Vec<(f64, f64, &Texture, u32)> = level
+            .sprites
+            .iter()
+            .filter(|level_sprite| {
+                editor.active
+                    || (level_sprite.kind != "enemy"
+                        && player::pickup_frame(&level_sprite.kind).is_none())
+            })
+            .map(|level_sprite| {
+                let (texture, frame) = if level_sprite.kind == "enemy" {
+                    (enemy_texture, 0)
+                } else if let Some(frame) = player::pickup_frame(&level_sprite.kind) {
+                    (items_texture, frame)
+                } else {
+                    (sprite, 0)
+                };
+                (level_sprite.x, level_sprite.y, texture, frame)
+            })
+            .collect();
+        if !editor.active {
+            sprites.extend(spawned.iter().map(|&(x, y)| (x, y, sprite, 0)));
+            sprites.extend(
+                enemies
+                    .iter()
+                    .map(|enemy| (enemy.x, enemy.y, enemy_texture, enemy.frame())),
+            );
+            sprites.extend(
+                pickups
+                    .iter()
+                    .map(|pickup| (pickup.x, pickup.y, items_texture, pickup.frame())),
+            );
+            sprites.extend(projectiles.projectiles.iter().map(|projectile| {
+                (
+                    projectile.x,
+                    projectile.y,
+                    projectile_texture,
+                    projectile.frame(),
+                )
+            }));
+            sprites.extend(
+                projectiles
+                    .effects
+                    .iter()
+                    .map(|effect| (effect.x, effect.y, projectile_texture, effect.frame())),
+            );
+        }
+        sprites.sort_by(|a, b| {
+            let dist_a = (a.0 - camx).powi(2) + (a.1 - camy).powi(2);
+            let dist_b = (b.0 - camx).powi(2) + (b.1 - camy).powi(2);
+            dist_b.total_cmp(&dist_a)
+        });
+        for (x, y, texture, frame) in sprites {
+            sprite::draw_sprite(&mut canvas, texture, &view, &depthbuffer, x, y, frame)?;
         }
 
-        canvas.set_draw_color(Color::WHITE);
-        for i in 0..MAP_HEIGHT {
-            for j in 0..MAP_WIDTH {
-                if MAP[(j + i * MAP_WIDTH) as usize] != 0 {
-                    canvas
-                        .draw_rect(Rect::new((j * 32) as i32, (i * 32) as i32, 32, 32))
-                        .unwrap();
-                }
+        if !editor.active {
+            view_model.draw(&mut canvas, weapon_texture, weapons.current)?;
+            canvas.set_draw_color(Color::WHITE);
+            canvas
+                .draw_line((394, 300), (406, 300))
+                .map_err(Error::Sdl)?;
+            canvas
+                .draw_line((400, 294), (400, 306))
+                .map_err(Error::Sdl)?;
+        }
+
+        if pickup_flash > 0.0 {
+            canvas.set_draw_color(Color::RGBA(255, 200, 0, (pickup_flash * 255.0) as u8));
+            canvas.fill_rect(None).map_err(Error::Sdl)?;
+        }
+        if hurt_flash > 0.0 {
+            canvas.set_draw_color(Color::RGBA(255, 0, 0, (hurt_flash * 255.0) as u8));
+            canvas.fill_rect(None).map_err(Error::Sdl)?;
+        }
+
+        minimap.update(camx, camy, cam_rotation, Rect::new(0, 0, 800, 600));
+        let minimap_layer = if editor.active {
+            editor.layer()
+        } else {
+            Layer::Walls
+        };
+        minimap.draw_cells(
+            &mut canvas,
+            &mut minimap_texture,
+            level.layer(minimap_layer),
+        )?;
+        for level_sprite in &level.sprites {
+            //Enemies and pickups that are still around are drawn below
+            if !editor.active
+                && (level_sprite.kind == "enemy"
+                    || player::pickup_frame(&level_sprite.kind).is_some())
+            {
+                continue;
             }
+            let color = match level_sprite.kind.as_str() {
+                "enemy" => Color::RED,
+                "decoration" => Color::GRAY,
+                _ => Color::GREEN,
+            };
+            minimap.draw_marker(&mut canvas, level_sprite.x, level_sprite.y, 0.5, color)?;
         }
 
-        canvas.set_draw_color(Color::RED);
-        canvas
-            .draw_rect(Rect::new(
-                (spritex * 32.0) as i32 - 8,
-                (spritey * 32.0) as i32 - 8,
-                16,
-                16,
-            ))
-            .unwrap();
+        if !editor.active {
+            for enemy in &enemies {
+                let color = if enemy.is_dead() {
+                    Color::RGB(100, 0, 0)
+                } else {
+                    Color::RED
+                };
+                minimap.draw_marker(&mut canvas, enemy.x, enemy.y, 0.5, color)?;
+            }
+            for pickup in &pickups {
+                minimap.draw_marker(&mut canvas, pickup.x, pickup.y, 0.5, Color::GREEN)?;
+            }
+            for projectile in &projectiles.projectiles {
+                minimap.draw_marker(
+                    &mut canvas,
+                    projectile.x,
+                    projectile.y,
+                    0.15,
+                    Color::YELLOW,
+                )?;
+            }
+        }
 
-        let mut angle = cam_rotation - FOV / 2.0;
-        for _ in 0..80 {
-            angle += FOV * 1.0 / 80.0;
-            let ray = raycast(camx, camy, angle, 64.0);
+        for &(x, y, _) in &impacts {
+            minimap.draw_marker(&mut canvas, x, y, 0.15, Color::WHITE)?;
+        }
 
-            canvas.set_draw_color(Color::WHITE);
+        if minimap.mode != MinimapMode::Off {
+            let mut angle = cam_rotation - FOV / 2.0;
+            for _ in 0..80 {
+                angle += FOV * 1.0 / 80.0;
+                let ray = raycast(&level, camx, camy, angle, 64.0);
 
-            if ray.tile_type != 0 {
-                canvas
-                    .draw_line(
-                        Point::new((camx * 32.0) as i32, (camy * 32.0) as i32),
-                        Point::new((ray.x * 32.0) as i32, (ray.y * 32.0) as i32),
-                    )
-                    .unwrap();
+                if ray.tile_type != 0 {
+                    minimap.draw_ray(&mut canvas, camx, camy, ray.x, ray.y)?;
+                }
+            }
+        }
+
+        if editor.active {
+            minimap.draw_ray(
+                &mut canvas,
+                level.start.x,
+                level.start.y,
+                level.start.x + level.start.angle.cos() * 0.5,
+                level.start.y + level.start.angle.sin() * 0.5,
+            )?;
+            minimap.draw_marker(&mut canvas, level.start.x, level.start.y, 0.5, Color::CYAN)?;
+            if let Some((x, y)) = editor.cursor {
+                minimap.draw_marker(
+                    &mut canvas,
+                    x as f64 + 0.5,
+                    y as f64 + 0.5,
+                    1.0,
+                    Color::MAGENTA,
+                )?;
             }
         }
+        minimap.draw_camera(&mut canvas, camx, camy, cam_rotation)?;
+        minimap.finish(&mut canvas);
+
+        hud.is_recording = recorder.is_recording();
+        if hud.visible || debug_overlay.enabled {
+            let drawn = hud_texture.with_lock(None, |pixels: &mut [u8], pitch: usize| {
+                let mut framebuffer = FrameBuffer::new(pixels, pitch);
+                framebuffer.clear(Color::RGBA(0, 0, 0, 0));
+                hud.draw(&font, &mut framebuffer, camx, camy, cam_rotation);
+                if !editor.active {
+                    hud.draw_status(&font, &mut framebuffer, &player, weapons.weapon());
+                }
+                //After the status bar so it does not cover the tile strip
+                debug_overlay.draw(&font, &mut framebuffer, &columns);
+                editor.draw(&hud, &font, &mut framebuffer);
+            });
+            drawn.map_err(Error::Sdl)?;
+            canvas.copy(&hud_texture, None, None).map_err(Error::Sdl)?;
+        }
+
+        if take_screenshot {
+            take_screenshot = false;
+            match capture::screenshot(&canvas) {
+                Ok(path) => println!("saved screenshot to {}", path.display()),
+                Err(msg) => eprintln!("failed to save screenshot: {msg}"),
+            }
+        }
+
+        if let Err(msg) = recorder.capture_frame(&canvas) {
+            eprintln!("failed to record frame: {msg}");
+            recorder.stop();
+        }
 
         canvas.present();
 
         dt = start.elapsed().as_secs_f64();
+        hud.update(dt);
     }
 
     Ok(())
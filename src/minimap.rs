@@ -0,0 +1,314 @@
+use crate::error::Error;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MinimapMode {
+    Off,
+    Corner,
+    Fullscreen,
+}
+
+impl MinimapMode {
+    //Cycle off -> corner -> fullscreen -> off
+    pub fn next(self) -> MinimapMode {
+        match self {
+            MinimapMode::Off => MinimapMode::Corner,
+            MinimapMode::Corner => MinimapMode::Fullscreen,
+            MinimapMode::Fullscreen => MinimapMode::Off,
+        }
+    }
+}
+
+//Transform from world coordinates to screen coordinates for one frame
+struct View {
+    viewport: Rect,
+    //World position that is drawn at the center of the viewport
+    originx: f64,
+    originy: f64,
+    //Pixels per map cell
+    scale: f64,
+    rotation: f64,
+}
+
+impl View {
+    fn to_screen(&self, x: f64, y: f64) -> Point {
+        let dx = (x - self.originx) * self.scale;
+        let dy = (y - self.originy) * self.scale;
+        let (sin, cos) = self.rotation.sin_cos();
+        Point::new(
+            (dx * cos - dy * sin) as i32 + self.viewport.center().x(),
+            (dx * sin + dy * cos) as i32 + self.viewport.center().y(),
+        )
+    }
+
+    fn to_world(&self, x: i32, y: i32) -> (f64, f64) {
+        let dx = (x - self.viewport.center().x()) as f64 / self.scale;
+        let dy = (y - self.viewport.center().y()) as f64 / self.scale;
+        let (sin, cos) = (-self.rotation).sin_cos();
+        (
+            dx * cos - dy * sin + self.originx,
+            dx * sin + dy * cos + self.originy,
+        )
+    }
+}
+
+pub struct Minimap {
+    pub mode: MinimapMode,
+    //Pixels per map cell in corner mode, fullscreen mode fits the whole map
+    pub scale: f64,
+    //Position and size of the minimap in corner mode
+    pub x: i32,
+    pub y: i32,
+    pub size: u32,
+    //Rotate the map so that the camera always faces up (corner mode only)
+    pub follow_rotation: bool,
+    //Hide cells that have not been seen yet
+    pub fog_of_war: bool,
+    //Color of each tile type, tile 0 is the color of empty floor
+    pub tile_colors: Vec<Color>,
+    pub fog_color: Color,
+    explored: Vec<bool>,
+    map_width: usize,
+    map_height: usize,
+    view: View,
+}
+
+impl Minimap {
+    pub fn new(map_width: usize, map_height: usize) -> Minimap {
+        Minimap {
+            mode: MinimapMode::Corner,
+            scale: 32.0,
+            x: 0,
+            y: 0,
+            size: 256,
+            follow_rotation: false,
+            fog_of_war: true,
+            tile_colors: vec![
+                Color::RGBA(40, 40, 40, 200),
+                Color::RGBA(160, 160, 160, 255),
+                Color::RGBA(170, 90, 60, 255),
+                Color::RGBA(70, 130, 200, 255),
+                Color::RGBA(80, 170, 80, 255),
+            ],
+            fog_color: Color::RGBA(0, 0, 0, 160),
+            explored: vec![false; map_width * map_height],
+            map_width,
+            map_height,
+            view: View {
+                viewport: Rect::new(0, 0, 1, 1),
+                originx: 0.0,
+                originy: 0.0,
+                scale: 1.0,
+                rotation: 0.0,
+            },
+        }
+    }
+
+    //Called when the level changes, exploration is kept if the size is the same
+    pub fn resize(&mut self, map_width: usize, map_height: usize) {
+        if map_width != self.map_width || map_height != self.map_height {
+            self.explored = vec![false; map_width * map_height];
+            self.map_width = map_width;
+            self.map_height = map_height;
+        }
+    }
+
+    pub fn is_explored(&self, x: isize, y: isize) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.map_width || y as usize >= self.map_height {
+            return false;
+        }
+
+        self.explored[x as usize + y as usize * self.map_width]
+    }
+
+    //Mark every cell along a ray from the camera to the wall it hit as explored
+    pub fn reveal_ray(&mut self, startx: f64, starty: f64, endx: f64, endy: f64) {
+        let len = ((endx - startx).powi(2) + (endy - starty).powi(2)).sqrt();
+        let steps = (len * 4.0).ceil() as usize + 1;
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            //Step slightly past the end so the wall cell itself is revealed
+            let x = startx + (endx - startx) * t * 1.01;
+            let y = starty + (endy - starty) * t * 1.01;
+            if x < 0.0 || y < 0.0 {
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+            if x < self.map_width && y < self.map_height {
+                self.explored[x + y * self.map_width] = true;
+            }
+        }
+    }
+
+    //Set up the transform for this frame, must be called before drawing
+    pub fn update(&mut self, camx: f64, camy: f64, cam_rotation: f64, screen: Rect) {
+        self.view = match self.mode {
+            MinimapMode::Fullscreen => View {
+                viewport: screen,
+                originx: self.map_width as f64 / 2.0,
+                originy: self.map_height as f64 / 2.0,
+                scale: (screen.width() as f64 / self.map_width as f64)
+                    .min(screen.height() as f64 / self.map_height as f64)
+                    * 0.9,
+                rotation: 0.0,
+            },
+            _ => View {
+                viewport: Rect::new(self.x, self.y, self.size, self.size),
+                originx: camx,
+                originy: camy,
+                scale: self.scale,
+                rotation: if self.follow_rotation {
+                    -PI / 2.0 - cam_rotation
+                } else {
+                    0.0
+                },
+            },
+        };
+    }
+
+    //World position under a screen position, None if it is not on the minimap
+    pub fn screen_to_world(&self, x: i32, y: i32) -> Option<(f64, f64)> {
+        if self.mode == MinimapMode::Off || !self.view.viewport.contains_point(Point::new(x, y)) {
+            return None;
+        }
+
+        Some(self.view.to_world(x, y))
+    }
+
+    fn tile_color(&self, tile: u8) -> Color {
+        self.tile_colors
+            .get(tile as usize)
+            .copied()
+            .unwrap_or(Color::WHITE)
+    }
+
+    //Draws the map cells into `texture` (a streaming BGRA8888 texture the size
+    //of the screen) and copies the minimap area onto the canvas
+    pub fn draw_cells(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture: &mut Texture,
+        map: &[u8],
+    ) -> Result<(), Error> {
+        if self.mode == MinimapMode::Off {
+            return Ok(());
+        }
+
+        let viewport = self.view.viewport;
+        let drawn = texture.with_lock(viewport, |pixels: &mut [u8], pitch: usize| {
+            for y in 0..viewport.height() as usize {
+                for x in 0..viewport.width() as usize {
+                    let (worldx, worldy) = self
+                        .view
+                        .to_world(x as i32 + viewport.x(), y as i32 + viewport.y());
+                    let (tilex, tiley) = (worldx.floor() as isize, worldy.floor() as isize);
+
+                    let color = if tilex < 0
+                        || tiley < 0
+                        || tilex as usize >= self.map_width
+                        || tiley as usize >= self.map_height
+                    {
+                        Color::RGBA(0, 0, 0, 0)
+                    } else if self.fog_of_war && !self.is_explored(tilex, tiley) {
+                        self.fog_color
+                    } else {
+                        self.tile_color(map[tilex as usize + tiley as usize * self.map_width])
+                    };
+
+                    let ind = y * pitch + x * 4;
+                    pixels[ind] = color.a;
+                    pixels[ind + 1] = color.r;
+                    pixels[ind + 2] = color.g;
+                    pixels[ind + 3] = color.b;
+                }
+            }
+        });
+        drawn.map_err(Error::Sdl)?;
+
+        canvas
+            .copy(texture, viewport, viewport)
+            .map_err(Error::Sdl)?;
+
+        if self.mode == MinimapMode::Corner {
+            canvas.set_draw_color(Color::WHITE);
+            canvas.draw_rect(viewport).map_err(Error::Sdl)?;
+        }
+
+        canvas.set_clip_rect(viewport);
+        Ok(())
+    }
+
+    pub fn draw_ray(
+        &self,
+        canvas: &mut Canvas<Window>,
+        startx: f64,
+        starty: f64,
+        endx: f64,
+        endy: f64,
+    ) -> Result<(), Error> {
+        if self.mode == MinimapMode::Off {
+            return Ok(());
+        }
+
+        canvas.set_draw_color(Color::WHITE);
+        canvas
+            .draw_line(
+                self.view.to_screen(startx, starty),
+                self.view.to_screen(endx, endy),
+            )
+            .map_err(Error::Sdl)
+    }
+
+    //Draws a box of `size` map cells centered on (x, y)
+    pub fn draw_marker(
+        &self,
+        canvas: &mut Canvas<Window>,
+        x: f64,
+        y: f64,
+        size: f64,
+        color: Color,
+    ) -> Result<(), Error> {
+        if self.mode == MinimapMode::Off {
+            return Ok(());
+        }
+
+        let sz = ((size * self.view.scale) as u32).max(2);
+        canvas.set_draw_color(color);
+        canvas
+            .draw_rect(Rect::from_center(self.view.to_screen(x, y), sz, sz))
+            .map_err(Error::Sdl)
+    }
+
+    //Draws the camera position and the direction it faces
+    pub fn draw_camera(
+        &self,
+        canvas: &mut Canvas<Window>,
+        camx: f64,
+        camy: f64,
+        cam_rotation: f64,
+    ) -> Result<(), Error> {
+        if self.mode == MinimapMode::Off {
+            return Ok(());
+        }
+
+        self.draw_marker(canvas, camx, camy, 0.25, Color::YELLOW)?;
+        canvas
+            .draw_line(
+                self.view.to_screen(camx, camy),
+                self.view.to_screen(
+                    camx + cam_rotation.cos() * 0.5,
+                    camy + cam_rotation.sin() * 0.5,
+                ),
+            )
+            .map_err(Error::Sdl)
+    }
+
+    //Must be called once all minimap drawing is done
+    pub fn finish(&self, canvas: &mut Canvas<Window>) {
+        canvas.set_clip_rect(None);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::error::Error;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+//Pack files bundle assets into one archive. All numbers are little endian.
+//
+//  header:  magic "RCPK", version u32, entry count u32
+//  entries: name length u16, name (utf-8), offset u64, stored size u64,
+//           size u64, compression u8 (0 = raw, 1 = zlib)
+//  data:    the blob of each entry at its offset from the start of the file
+const MAGIC: &[u8; 4] = b"RCPK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 12;
+//Size of an entry in the table of contents without its name
+const ENTRY_SIZE: u64 = 2 + 8 + 8 + 8 + 1;
+//Entries are read into memory whole, bigger ones are taken to be corrupt
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Raw,
+    Zlib,
+}
+
+struct PackEntry {
+    name: String,
+    offset: u64,
+    stored_size: u64,
+    size: u64,
+    encoding: Encoding,
+}
+
+pub struct Pack {
+    pub path: PathBuf,
+    entries: Vec<PackEntry>,
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+    move |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+fn invalid_pack(path: &Path, message: &str) -> Error {
+    Error::InvalidPack {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+impl Pack {
+    //Reads the table of contents, entries are only read when they are used
+    pub fn open(path: &Path) -> Result<Pack, Error> {
+        let file = File::open(path).map_err(|source| Error::AssetNotFound {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let file_size = file.metadata().map_err(io_error(path))?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).map_err(io_error(path))?;
+        if &magic != MAGIC {
+            return Err(invalid_pack(path, "not a pack file"));
+        }
+
+        let version = read_u32(&mut reader).map_err(io_error(path))?;
+        if version != VERSION {
+            return Err(invalid_pack(
+                path,
+                &format!("unsupported version {version}"),
+            ));
+        }
+
+        let count = read_u32(&mut reader).map_err(io_error(path))?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let name_len = read_u16(&mut reader).map_err(io_error(path))?;
+            let mut name = vec![0u8; name_len as usize];
+            reader.read_exact(&mut name).map_err(io_error(path))?;
+            let name = String::from_utf8(name)
+                .map_err(|_| invalid_pack(path, "entry name is not valid utf-8"))?;
+
+            let offset = read_u64(&mut reader).map_err(io_error(path))?;
+            let stored_size = read_u64(&mut reader).map_err(io_error(path))?;
+            let size = read_u64(&mut reader).map_err(io_error(path))?;
+            let mut encoding = [0u8; 1];
+            reader.read_exact(&mut encoding).map_err(io_error(path))?;
+            let encoding = match encoding[0] {
+                0 => Encoding::Raw,
+                1 => Encoding::Zlib,
+                _ => {
+                    return Err(invalid_pack(
+                        path,
+                        &format!("{name} has unknown compression"),
+                    ))
+                }
+            };
+
+            if offset
+                .checked_add(stored_size)
+                .is_none_or(|end| end > file_size)
+            {
+                return Err(invalid_pack(
+                    path,
+                    &format!("{name} is outside of the file"),
+                ));
+            }
+            if size > MAX_ENTRY_SIZE || (encoding == Encoding::Raw && size != stored_size) {
+                return Err(invalid_pack(path, &format!("{name} has an invalid size")));
+            }
+
+            entries.push(PackEntry {
+                name,
+                offset,
+                stored_size,
+                size,
+                encoding,
+            });
+        }
+
+        Ok(Pack {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| entry.name == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    //Reads and decompresses the entry called `name`
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| Error::AssetNotFound {
+                path: self.path.join(name),
+                source: io::Error::new(io::ErrorKind::NotFound, "not in pack file"),
+            })?;
+
+        let mut file = File::open(&self.path).map_err(io_error(&self.path))?;
+        file.seek(SeekFrom::Start(entry.offset))
+            .map_err(io_error(&self.path))?;
+        let blob = file.take(entry.stored_size);
+
+        //Decompress one byte more than expected so that an entry that is too
+        //big is caught without reading all of it
+        let mut data = Vec::with_capacity(entry.size as usize);
+        match entry.encoding {
+            Encoding::Raw => BufReader::new(blob).read_to_end(&mut data),
+            Encoding::Zlib => ZlibDecoder::new(blob)
+                .take(entry.size + 1)
+                .read_to_end(&mut data),
+        }
+        .map_err(io_error(&self.path))?;
+
+        if data.len() as u64 != entry.size {
+            return Err(invalid_pack(
+                &self.path,
+                &format!("{name} has the wrong size"),
+            ));
+        }
+
+        Ok(data)
+    }
+}
+
+//Files to pack and the names they are stored under. Directories are packed
+//recursively with names relative to the directory, `/` separates directories.
+pub fn collect_files(inputs: &[PathBuf]) -> Result<Vec<(String, PathBuf)>, Error> {
+    fn visit(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<(), Error> {
+        let mut entries = fs::read_dir(dir)
+            .map_err(io_error(dir))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_error(dir))?;
+        //Sort so that packing the same files always gives the same pack
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            if path.is_dir() {
+                visit(&path, &format!("{name}/"), files)?;
+            } else {
+                files.push((name, path));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            visit(input, "", &mut files)?;
+        } else if input.is_file() {
+            let name = input
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            files.push((name, input.clone()));
+        } else {
+            return Err(Error::AssetNotFound {
+                path: input.clone(),
+                source: io::Error::new(io::ErrorKind::NotFound, "no such file or directory"),
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+//Writes a pack file, when `compress` is set each file is stored compressed
+//unless that would make it bigger
+pub fn write_pack(output: &Path, files: &[(String, PathBuf)], compress: bool) -> Result<(), Error> {
+    let mut blobs = Vec::new();
+    for (name, path) in files {
+        if name.len() > u16::MAX as usize {
+            return Err(invalid_pack(output, &format!("name is too long: {name}")));
+        }
+
+        let data = fs::read(path).map_err(|source| Error::AssetNotFound {
+            path: path.clone(),
+            source,
+        })?;
+
+        let mut blob = (Encoding::Raw, data.clone());
+        if compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(&data).map_err(io_error(path))?;
+            let compressed = encoder.finish().map_err(io_error(path))?;
+            if compressed.len() < data.len() {
+                blob = (Encoding::Zlib, compressed);
+            }
+        }
+        blobs.push((name, data.len() as u64, blob));
+    }
+
+    let toc_size: u64 = blobs
+        .iter()
+        .map(|(name, _, _)| ENTRY_SIZE + name.len() as u64)
+        .sum();
+
+    let file = File::create(output).map_err(io_error(output))?;
+    let mut writer = BufWriter::new(file);
+    let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(io_error(output));
+
+    write(MAGIC)?;
+    write(&VERSION.to_le_bytes())?;
+    write(&(blobs.len() as u32).to_le_bytes())?;
+
+    let mut offset = HEADER_SIZE + toc_size;
+    for (name, size, (encoding, data)) in &blobs {
+        write(&(name.len() as u16).to_le_bytes())?;
+        write(name.as_bytes())?;
+        write(&offset.to_le_bytes())?;
+        write(&(data.len() as u64).to_le_bytes())?;
+        write(&size.to_le_bytes())?;
+        write(&[match encoding {
+            Encoding::Raw => 0,
+            Encoding::Zlib => 1,
+        }])?;
+        offset += data.len() as u64;
+    }
+
+    for (_, _, (_, data)) in &blobs {
+        write(data)?;
+    }
+
+    writer.flush().map_err(io_error(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("raycast-pack-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("levels")).unwrap();
+        let contents: [(&str, Vec<u8>); 3] = [
+            ("empty.txt", Vec::new()),
+            ("levels/big.lvl", b"0 1 ".repeat(1000)),
+            ("noise.bin", (0..=255u8).rev().collect()),
+        ];
+        for (name, data) in &contents {
+            fs::write(dir.join(name), data).unwrap();
+        }
+
+        let output = dir.with_extension("pak");
+        let files = collect_files(std::slice::from_ref(&dir)).unwrap();
+        write_pack(&output, &files, true).unwrap();
+        let pack = Pack::open(&output).unwrap();
+        let names: Vec<&str> = pack.names().collect();
+        assert_eq!(names, ["empty.txt", "levels/big.lvl", "noise.bin"]);
+        for (name, data) in &contents {
+            assert_eq!(&pack.read(name).unwrap(), data);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+use crate::level::Level;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+//Cost of a step to a neighbouring cell, diagonal steps cost about sqrt(2) as
+//much
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+//Cached paths are forgotten once there are more than this many
+const MAX_CACHED_PATHS: usize = 256;
+const MAX_CACHED_FIELDS: usize = 16;
+
+//Position of a cell of the level grid
+pub type Cell = (usize, usize);
+
+//Which cells can be walked through and how actors may step between them
+#[derive(Clone, Copy)]
+pub struct Rules {
+    //Allow diagonal steps, never past the corner of a wall
+    pub diagonal: bool,
+    //Closed doors count as open, for actors that can open doors
+    pub through_doors: bool,
+}
+
+impl Rules {
+    fn walkable(&self, level: &Level, x: isize, y: isize) -> bool {
+        if x < 0 || y < 0 || x as usize >= level.width || y as usize >= level.height {
+            return false;
+        }
+
+        //Moving push walls block every cell they pass through until they stop
+        if level.pushwalls.iter().any(|wall| wall.crosses(x, y)) {
+            return false;
+        }
+
+        level.get_tile(x, y) == 0
+            || (self.through_doors
+                && level
+                    .doors
+                    .iter()
+                    .any(|door| door.x as isize == x && door.y as isize == y))
+    }
+
+    //Cells that can be reached in one step from `cell` and the cost of the step
+    fn neighbours(&self, level: &Level, cell: Cell) -> Vec<(Cell, u32)> {
+        let (x, y) = (cell.0 as isize, cell.1 as isize);
+        let mut neighbours = Vec::with_capacity(8);
+        for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+            if self.walkable(level, x + dx, y + dy) {
+                neighbours.push((((x + dx) as usize, (y + dy) as usize), STRAIGHT_COST));
+            }
+        }
+
+        if self.diagonal {
+            for (dx, dy) in [(1, 1), (-1, 1), (-1, -1), (1, -1)] {
+                //Both cells next to the diagonal must be open so that the step
+                //does not cut through the corner of a wall
+                if self.walkable(level, x + dx, y + dy)
+                    && self.walkable(level, x + dx, y)
+                    && self.walkable(level, x, y + dy)
+                {
+                    neighbours.push((((x + dx) as usize, (y + dy) as usize), DIAGONAL_COST));
+                }
+            }
+        }
+
+        neighbours
+    }
+
+    //Lower bound of the cost from `from` to `to`
+    fn estimate(&self, from: Cell, to: Cell) -> u32 {
+        let dx = from.0.abs_diff(to.0) as u32;
+        let dy = from.1.abs_diff(to.1) as u32;
+        if self.diagonal {
+            DIAGONAL_COST * dx.min(dy) + STRAIGHT_COST * (dx.max(dy) - dx.min(dy))
+        } else {
+            STRAIGHT_COST * (dx + dy)
+        }
+    }
+}
+
+//Shortest path from `from` to `to` with A*, both ends included. None if `to`
+//can not be reached or either end is not walkable.
+pub fn find_path(level: &Level, rules: Rules, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+    if !rules.walkable(level, from.0 as isize, from.1 as isize)
+        || !rules.walkable(level, to.0 as isize, to.1 as isize)
+    {
+        return None;
+    }
+
+    let index = |cell: Cell| cell.0 + cell.1 * level.width;
+    let mut cost = vec![u32::MAX; level.width * level.height];
+    let mut came_from = vec![usize::MAX; level.width * level.height];
+    let mut open = BinaryHeap::new();
+    cost[index(from)] = 0;
+    open.push(Reverse((rules.estimate(from, to), index(from))));
+
+    while let Some(Reverse((_, current))) = open.pop() {
+        let cell = (current % level.width, current / level.width);
+        if cell == to {
+            let mut path = vec![to];
+            let mut ind = current;
+            while came_from[ind] != usize::MAX {
+                ind = came_from[ind];
+                path.push((ind % level.width, ind / level.width));
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for (next, step) in rules.neighbours(level, cell) {
+            let next_cost = cost[current] + step;
+            if next_cost < cost[index(next)] {
+                cost[index(next)] = next_cost;
+                came_from[index(next)] = current;
+                open.push(Reverse((next_cost + rules.estimate(next, to), index(next))));
+            }
+        }
+    }
+
+    None
+}
+
+//Cost of the shortest path from every cell to one goal, so any number of
+//actors heading to the same place only need one search
+pub struct FlowField {
+    rules: Rules,
+    width: usize,
+    cost: Vec<u32>,
+}
+
+impl FlowField {
+    //Dijkstra outwards from the goal, steps cost the same both ways so the
+    //result is the cost of getting to the goal
+    pub fn new(level: &Level, rules: Rules, goal: Cell) -> FlowField {
+        let mut field = FlowField {
+            rules,
+            width: level.width,
+            cost: vec![u32::MAX; level.width * level.height],
+        };
+        if !rules.walkable(level, goal.0 as isize, goal.1 as isize) {
+            return field;
+        }
+
+        let mut open = BinaryHeap::new();
+        field.cost[goal.0 + goal.1 * level.width] = 0;
+        open.push(Reverse((0, goal)));
+        while let Some(Reverse((cost, cell))) = open.pop() {
+            if cost > field.cost[cell.0 + cell.1 * level.width] {
+                continue;
+            }
+
+            for (next, step) in rules.neighbours(level, cell) {
+                let ind = next.0 + next.1 * level.width;
+                if cost + step < field.cost[ind] {
+                    field.cost[ind] = cost + step;
+                    open.push(Reverse((cost + step, next)));
+                }
+            }
+        }
+
+        field
+    }
+
+    //Cost of getting from `cell` to the goal, None if it can not be reached
+    pub fn cost(&self, cell: Cell) -> Option<u32> {
+        self.cost
+            .get(cell.0 + cell.1 * self.width)
+            .copied()
+            .filter(|&cost| cost != u32::MAX)
+    }
+
+    //Neighbour of `cell` to step to on the way to the goal, None at the goal
+    //or when it can not be reached
+    pub fn next(&self, level: &Level, cell: Cell) -> Option<Cell> {
+        let cost = self.cost(cell)?;
+        self.rules
+            .neighbours(level, cell)
+            .into_iter()
+            .filter_map(|(next, _)| self.cost(next).map(|next_cost| (next_cost, next)))
+            .filter(|&(next_cost, _)| next_cost < cost)
+            .min()
+            .map(|(_, next)| next)
+    }
+}
+
+//Finds paths for the actors of a level and remembers them. Everything cached
+//is thrown away as soon as the walls of the level change, for example when a
+//door opens or a push wall starts or stops moving.
+pub struct Pathfinder {
+    pub rules: Rules,
+    //Revision of the level the cache is for
+    revision: Option<u64>,
+    //Paths found so far by their goal, a path also leads to the goal from
+    //every cell along it
+    paths: HashMap<Cell, Vec<Vec<Cell>>>,
+    path_count: usize,
+    fields: HashMap<Cell, FlowField>,
+}
+
+impl Pathfinder {
+    pub fn new(rules: Rules) -> Pathfinder {
+        Pathfinder {
+            rules,
+            revision: None,
+            paths: HashMap::new(),
+            path_count: 0,
+            fields: HashMap::new(),
+        }
+    }
+
+    fn check_level(&mut self, level: &Level) {
+        if self.revision != Some(level.revision()) {
+            self.revision = Some(level.revision());
+            self.paths.clear();
+            self.path_count = 0;
+            self.fields.clear();
+        }
+    }
+
+    //Like `find_path` but reuses the part of an earlier path to `to` that
+    //starts at `from`
+    pub fn path(&mut self, level: &Level, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        self.check_level(level);
+        if let Some(path) = self.paths.get(&to).and_then(|paths| {
+            paths.iter().find_map(|path| {
+                path.iter()
+                    .position(|&cell| cell == from)
+                    .map(|i| &path[i..])
+            })
+        }) {
+            return Some(path.to_vec());
+        }
+
+        let path = find_path(level, self.rules, from, to)?;
+        if self.path_count >= MAX_CACHED_PATHS {
+            self.paths.clear();
+            self.path_count = 0;
+        }
+        self.paths.entry(to).or_default().push(path.clone());
+        self.path_count += 1;
+        Some(path)
+    }
+
+    //Flow field towards `goal`, shared by every actor heading there
+    pub fn flow_field(&mut self, level: &Level, goal: Cell) -> &FlowField {
+        self.check_level(level);
+        if self.fields.len() >= MAX_CACHED_FIELDS && !self.fields.contains_key(&goal) {
+            self.fields.clear();
+        }
+        let rules = self.rules;
+        self.fields
+            .entry(goal)
+            .or_insert_with(|| FlowField::new(level, rules, goal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRAIGHT: Rules = Rules {
+        diagonal: false,
+        through_doors: false,
+    };
+    const DIAGONAL: Rules = Rules {
+        diagonal: true,
+        through_doors: false,
+    };
+
+    //Builds a level from rows of `#` for walls and `.` for empty cells
+    fn grid(rows: &[&str]) -> Level {
+        let walls: Vec<u8> = rows
+            .iter()
+            .flat_map(|row| row.bytes())
+            .map(|cell| u8::from(cell == b'#'))
+            .collect();
+        let size = walls.len();
+        Level::new(
+            rows[0].len(),
+            rows.len(),
+            walls,
+            vec![0; size],
+            vec![0; size],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let level = grid(&["#####", "#...#", "#.#.#", "#...#", "#####"]);
+        let path = find_path(&level, STRAIGHT, (1, 1), (3, 3)).unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!((path[0], path[4]), ((1, 1), (3, 3)));
+        for step in path.windows(2) {
+            assert_eq!(
+                step[0].0.abs_diff(step[1].0) + step[0].1.abs_diff(step[1].1),
+                1
+            );
+            assert_eq!(level.get_tile(step[1].0 as isize, step[1].1 as isize), 0);
+        }
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        let level = grid(&["#####", "#.#.#", "#####"]);
+        assert_eq!(find_path(&level, DIAGONAL, (1, 1), (3, 1)), None);
+        assert_eq!(find_path(&level, DIAGONAL, (1, 1), (2, 1)), None);
+
+        let field = FlowField::new(&level, DIAGONAL, (3, 1));
+        assert_eq!(field.cost((3, 1)), Some(0));
+        assert_eq!(field.cost((1, 1)), None);
+        assert_eq!(field.next(&level, (1, 1)), None);
+    }
+
+    #[test]
+    fn diagonal_steps_do_not_cut_corners() {
+        let level = grid(&["####", "#.##", "##.#", "####"]);
+        assert_eq!(find_path(&level, DIAGONAL, (1, 1), (2, 2)), None);
+        assert_eq!(FlowField::new(&level, DIAGONAL, (2, 2)).cost((1, 1)), None);
+
+        let level = grid(&["####", "#..#", "##.#", "####"]);
+        assert_eq!(
+            find_path(&level, DIAGONAL, (1, 1), (2, 2)),
+            Some(vec![(1, 1), (2, 1), (2, 2)])
+        );
+        let field = FlowField::new(&level, DIAGONAL, (2, 2));
+        assert_eq!(field.next(&level, (1, 1)), Some((2, 1)));
+
+        let level = grid(&["####", "#..#", "#..#", "####"]);
+        assert_eq!(
+            find_path(&level, DIAGONAL, (1, 1), (2, 2)),
+            Some(vec![(1, 1), (2, 2)])
+        );
+    }
+
+    #[test]
+    fn flow_field_leads_to_the_goal() {
+        let level = grid(&["#######", "#.....#", "#.###.#", "#.....#", "#######"]);
+        let field = FlowField::new(&level, DIAGONAL, (5, 3));
+        let mut cell = (1, 1);
+        let mut cost = field.cost(cell).unwrap();
+        while let Some(next) = field.next(&level, cell) {
+            let next_cost = field.cost(next).unwrap();
+            assert!(next_cost < cost);
+            (cell, cost) = (next, next_cost);
+        }
+        assert_eq!(cell, (5, 3));
+    }
+
+    #[test]
+    fn cache_follows_doors() {
+        let mut level = grid(&["#####", "#.#.#", "#####"]);
+        level.add_door(2, 1, None).unwrap();
+        let mut pathfinder = Pathfinder::new(STRAIGHT);
+        assert_eq!(pathfinder.path(&level, (1, 1), (3, 1)), None);
+
+        level.toggle_door(2, 1);
+        assert_eq!(
+            pathfinder.path(&level, (1, 1), (3, 1)),
+            Some(vec![(1, 1), (2, 1), (3, 1)])
+        );
+        assert!(pathfinder.flow_field(&level, (3, 1)).cost((1, 1)).is_some());
+
+        level.toggle_door(2, 1);
+        assert_eq!(pathfinder.path(&level, (1, 1), (3, 1)), None);
+        assert!(pathfinder.flow_field(&level, (3, 1)).cost((1, 1)).is_none());
+
+        let through_doors = Rules {
+            through_doors: true,
+            ..STRAIGHT
+        };
+        assert!(find_path(&level, through_doors, (1, 1), (3, 1)).is_some());
+    }
+
+    #[test]
+    fn moving_push_walls_block_paths() {
+        let mut level = grid(&["#######", "#.#...#", "#######"]);
+        level.add_pushwall(2, 1).unwrap();
+        let mut pathfinder = Pathfinder::new(STRAIGHT);
+        assert_eq!(pathfinder.path(&level, (1, 1), (3, 1)), None);
+
+        //Every cell the wall passes through stays blocked until it stops
+        assert!(level.push_wall(2, 1, (1, 0), &[]));
+        assert_eq!(pathfinder.path(&level, (1, 1), (3, 1)), None);
+        assert_eq!(find_path(&level, STRAIGHT, (5, 1), (3, 1)), None);
+
+        level.update_pushwalls(10.0);
+        assert_eq!(
+            pathfinder.path(&level, (1, 1), (3, 1)),
+            Some(vec![(1, 1), (2, 1), (3, 1)])
+        );
+        assert_eq!(pathfinder.path(&level, (1, 1), (5, 1)), None);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::level::Level;
+
+pub const MAX_HEALTH: i32 = 100;
+pub const MAX_ARMOR: i32 = 100;
+//Pickups are collected when the player comes this close to them
+const PICKUP_RADIUS: f64 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AmmoKind {
+    Bullets,
+    Rockets,
+}
+
+impl AmmoKind {
+    fn max(self) -> u32 {
+        match self {
+            AmmoKind::Bullets => 200,
+            AmmoKind::Rockets => 50,
+        }
+    }
+}
+
+pub struct Player {
+    pub health: i32,
+    pub armor: i32,
+    pub bullets: u32,
+    pub rockets: u32,
+    //Kinds of the items carried, such as keys
+    pub inventory: Vec<String>,
+}
+
+impl Player {
+    pub fn new() -> Player {
+        Player {
+            health: MAX_HEALTH,
+            armor: 0,
+            bullets: 50,
+            rockets: 0,
+            inventory: Vec::new(),
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0
+    }
+
+    //Armor takes a third of the damage for as long as it lasts
+    pub fn hurt(&mut self, damage: i32) {
+        let absorbed = (damage / 3).min(self.armor);
+        self.armor -= absorbed;
+        self.health = (self.health - damage + absorbed).max(0);
+    }
+
+    pub fn ammo(&self, kind: AmmoKind) -> u32 {
+        match kind {
+            AmmoKind::Bullets => self.bullets,
+            AmmoKind::Rockets => self.rockets,
+        }
+    }
+
+    fn ammo_mut(&mut self, kind: AmmoKind) -> &mut u32 {
+        match kind {
+            AmmoKind::Bullets => &mut self.bullets,
+            AmmoKind::Rockets => &mut self.rockets,
+        }
+    }
+
+    //Uses up one round, returns false if there is none left
+    pub fn use_ammo(&mut self, kind: AmmoKind) -> bool {
+        let ammo = self.ammo_mut(kind);
+        if *ammo == 0 {
+            return false;
+        }
+        *ammo -= 1;
+        true
+    }
+
+    fn add_ammo(&mut self, kind: AmmoKind, amount: u32) -> bool {
+        let ammo = self.ammo_mut(kind);
+        if *ammo >= kind.max() {
+            return false;
+        }
+        *ammo = (*ammo + amount).min(kind.max());
+        true
+    }
+
+    pub fn has_item(&self, kind: &str) -> bool {
+        self.inventory.iter().any(|item| item == kind)
+    }
+
+    //Takes a pickup of `kind`, returns false if it is of no use right now and
+    //stays where it is
+    pub fn pick_up(&mut self, kind: &str) -> bool {
+        match kind {
+            "health" if self.health < MAX_HEALTH => {
+                self.health = (self.health + 25).min(MAX_HEALTH);
+                true
+            }
+            "armor" if self.armor < MAX_ARMOR => {
+                self.armor = (self.armor + 50).min(MAX_ARMOR);
+                true
+            }
+            "ammo" => self.add_ammo(AmmoKind::Bullets, 20),
+            "rockets" => self.add_ammo(AmmoKind::Rockets, 5),
+            "key_gold" | "key_silver" if !self.has_item(kind) => {
+                self.inventory.push(String::from(kind));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+//Sprite kinds that can be picked up and their frames in items.png
+const PICKUP_KINDS: [&str; 6] = [
+    "health",
+    "ammo",
+    "rockets",
+    "armor",
+    "key_gold",
+    "key_silver",
+];
+
+//Frame of items.png for a sprite kind, None if it can not be picked up
+pub fn pickup_frame(kind: &str) -> Option<u32> {
+    PICKUP_KINDS
+        .iter()
+        .position(|&pickup| pickup == kind)
+        .map(|frame| frame as u32)
+}
+
+pub struct Pickup {
+    pub kind: String,
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Pickup {
+    pub fn frame(&self) -> u32 {
+        pickup_frame(&self.kind).unwrap_or(0)
+    }
+}
+
+//A pickup for every sprite of a pickup kind in the level
+pub fn spawn_pickups(level: &Level) -> Vec<Pickup> {
+    level
+        .sprites
+        .iter()
+        .filter(|sprite| pickup_frame(&sprite.kind).is_some())
+        .map(|sprite| Pickup {
+            kind: sprite.kind.clone(),
+            x: sprite.x,
+            y: sprite.y,
+        })
+        .collect()
+}
+
+//Gives the player every pickup close to (x, y) they can use, returns true if
+//anything was picked up
+pub fn collect(pickups: &mut Vec<Pickup>, player: &mut Player, x: f64, y: f64) -> bool {
+    let count = pickups.len();
+    pickups.retain(|pickup| {
+        let close = (pickup.x - x).powi(2) + (pickup.y - y).powi(2) < PICKUP_RADIUS.powi(2);
+        !(close && player.pick_up(&pickup.kind))
+    });
+    pickups.len() != count
+}
@@ -0,0 +1,238 @@
+use crate::decal::{DecalKind, Decals};
+use crate::enemy::Enemy;
+use crate::level::Level;
+use crate::raycast::raycast;
+
+//How far a projectile moves between collision checks, less than a cell so
+//fast projectiles can not pass through walls
+const MAX_STEP: f64 = 0.1;
+//Actors are hit when a projectile comes this close to their center
+const ACTOR_RADIUS: f64 = 0.3;
+//Length of the impact effect
+const EFFECT_TIME: f64 = 0.3;
+//Height projectiles fly at, where projectile.png draws them
+const HEIGHT: f64 = 0.45;
+
+//Frames of projectile.png
+const FIREBALL_FRAME: u32 = 0;
+const ROCKET_FRAME: u32 = 2;
+const EFFECT_FRAME: u32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProjectileKind {
+    Fireball,
+    Rocket,
+}
+
+impl ProjectileKind {
+    //Cells per second
+    fn speed(self) -> f64 {
+        match self {
+            ProjectileKind::Fireball => 5.0,
+            ProjectileKind::Rocket => 10.0,
+        }
+    }
+
+    fn damage(self) -> i32 {
+        match self {
+            ProjectileKind::Fireball => 10,
+            ProjectileKind::Rocket => 40,
+        }
+    }
+
+    //Everything within this distance of the impact is hurt too, with less
+    //damage further out
+    fn splash_radius(self) -> f64 {
+        match self {
+            ProjectileKind::Fireball => 0.0,
+            ProjectileKind::Rocket => 1.5,
+        }
+    }
+}
+
+pub struct Projectile {
+    pub kind: ProjectileKind,
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+    //Fired by the player, these hit enemies and the others hit the player
+    pub from_player: bool,
+    time: f64,
+}
+
+impl Projectile {
+    pub fn new(kind: ProjectileKind, x: f64, y: f64, angle: f64, from_player: bool) -> Projectile {
+        Projectile {
+            kind,
+            x,
+            y,
+            angle,
+            from_player,
+            time: 0.0,
+        }
+    }
+
+    //Frame of projectile.png to draw, fireballs flicker
+    pub fn frame(&self) -> u32 {
+        match self.kind {
+            ProjectileKind::Fireball => FIREBALL_FRAME + (self.time * 10.0) as u32 % 2,
+            ProjectileKind::Rocket => ROCKET_FRAME,
+        }
+    }
+}
+
+//The explosion left where a projectile hit something
+pub struct Effect {
+    pub x: f64,
+    pub y: f64,
+    time: f64,
+}
+
+impl Effect {
+    pub fn frame(&self) -> u32 {
+        EFFECT_FRAME + ((self.time / EFFECT_TIME * 3.0) as u32).min(2)
+    }
+}
+
+//What a projectile ran into
+enum Collision {
+    Wall,
+    Player,
+    Enemy(usize),
+}
+
+fn collide(
+    projectile: &Projectile,
+    level: &Level,
+    playerx: f64,
+    playery: f64,
+    enemies: &[Enemy],
+) -> Option<Collision> {
+    if level.is_solid(projectile.x, projectile.y)
+        || projectile.x < 0.0
+        || projectile.y < 0.0
+        || projectile.x >= level.width as f64
+        || projectile.y >= level.height as f64
+    {
+        return Some(Collision::Wall);
+    }
+
+    let near = |x: f64, y: f64| {
+        (x - projectile.x).powi(2) + (y - projectile.y).powi(2) < ACTOR_RADIUS.powi(2)
+    };
+    if projectile.from_player {
+        enemies
+            .iter()
+            .position(|enemy| !enemy.is_dead() && near(enemy.x, enemy.y))
+            .map(Collision::Enemy)
+    } else if near(playerx, playery) {
+        Some(Collision::Player)
+    } else {
+        None
+    }
+}
+
+//Projectiles in flight and the explosions of the ones that hit something
+pub struct Projectiles {
+    pub projectiles: Vec<Projectile>,
+    pub effects: Vec<Effect>,
+}
+
+impl Projectiles {
+    pub fn new() -> Projectiles {
+        Projectiles {
+            projectiles: Vec::new(),
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn fire(&mut self, projectile: Projectile) {
+        self.projectiles.push(projectile);
+    }
+
+    pub fn clear(&mut self) {
+        self.projectiles.clear();
+        self.effects.clear();
+    }
+
+    //Moves every projectile and effect on by `dt` seconds. Projectiles that
+    //hit something are replaced with an effect and hurt what they hit, those
+    //that hit a wall leave a scorch mark. The damage done to the player at
+    //(playerx, playery) is returned.
+    pub fn update(
+        &mut self,
+        level: &Level,
+        dt: f64,
+        (playerx, playery): (f64, f64),
+        enemies: &mut [Enemy],
+        decals: &mut Decals,
+    ) -> i32 {
+        for effect in self.effects.iter_mut() {
+            effect.time += dt;
+        }
+        self.effects.retain(|effect| effect.time < EFFECT_TIME);
+
+        let effects = &mut self.effects;
+        let mut player_damage = 0;
+        self.projectiles.retain_mut(|projectile| {
+            projectile.time += dt;
+            let dist = projectile.kind.speed() * dt;
+            let steps = (dist / MAX_STEP).ceil().max(1.0) as usize;
+            let (sin, cos) = projectile.angle.sin_cos();
+            for _ in 0..steps {
+                let (lastx, lasty) = (projectile.x, projectile.y);
+                projectile.x += cos * dist / steps as f64;
+                projectile.y += sin * dist / steps as f64;
+
+                let collision = match collide(projectile, level, playerx, playery, enemies) {
+                    Some(collision) => collision,
+                    None => continue,
+                };
+                //Explode in front of walls rather than inside them
+                if let Collision::Wall = collision {
+                    (projectile.x, projectile.y) = (lastx, lasty);
+                    let ray = raycast(level, lastx, lasty, projectile.angle, MAX_STEP * 2.0);
+                    if ray.tile_type != 0 {
+                        decals.add(&ray, HEIGHT, projectile.angle, DecalKind::Scorch);
+                    }
+                }
+
+                let damage = projectile.kind.damage();
+                match collision {
+                    Collision::Player => player_damage += damage,
+                    Collision::Enemy(index) => enemies[index].hurt(damage),
+                    Collision::Wall => {}
+                }
+
+                let radius = projectile.kind.splash_radius();
+                if radius > 0.0 {
+                    let splash = |x: f64, y: f64| {
+                        let dist = ((x - projectile.x).powi(2) + (y - projectile.y).powi(2)).sqrt();
+                        (damage as f64 * (1.0 - dist / radius)).max(0.0) as i32
+                    };
+                    for (index, enemy) in enemies.iter_mut().enumerate() {
+                        if !matches!(collision, Collision::Enemy(hit) if hit == index) {
+                            let damage = splash(enemy.x, enemy.y);
+                            if damage > 0 {
+                                enemy.hurt(damage);
+                            }
+                        }
+                    }
+                    if !matches!(collision, Collision::Player) {
+                        player_damage += splash(playerx, playery);
+                    }
+                }
+
+                effects.push(Effect {
+                    x: projectile.x,
+                    y: projectile.y,
+                    time: 0.0,
+                });
+                return false;
+            }
+            true
+        });
+
+        player_damage
+    }
+}
@@ -0,0 +1,217 @@
+use crate::level::Level;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Raycast {
+    pub x: f64,
+    pub y: f64,
+    pub tile_type: u8,
+    //The face that was hit runs along y, for walls in the grid x is then a
+    //whole number
+    pub vertical: bool,
+    //Position along the face from 0 to 1, the column of the wall texture
+    pub u: f64,
+}
+
+fn dist(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
+    ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt()
+}
+
+pub fn raycast(level: &Level, startx: f64, starty: f64, angle: f64, max_dist: f64) -> Raycast {
+    let mut vert = Raycast {
+        x: 0.0,
+        y: 0.0,
+        tile_type: 0,
+        vertical: true,
+        u: 0.0,
+    };
+
+    //Check vertical lines
+    if angle.cos() > 0.0 {
+        let mut rayx = startx.ceil();
+        let mut rayy = (rayx - startx) * angle.tan() + starty;
+        while (startx - rayx).abs() < max_dist {
+            let tile_type = level.get_tile(rayx as isize, rayy.floor() as isize);
+
+            if tile_type != 0 {
+                vert = Raycast {
+                    x: rayx,
+                    y: rayy,
+                    tile_type,
+                    vertical: true,
+                    u: rayy.fract(),
+                };
+
+                break;
+            }
+
+            rayx += 1.0;
+            rayy += angle.tan();
+        }
+    } else if angle.cos() < 0.0 {
+        let mut rayx = startx.floor();
+        let mut rayy = (rayx - startx) * angle.tan() + starty;
+        while (startx - rayx).abs() < max_dist {
+            let tile_type = level.get_tile(rayx as isize - 1, rayy.floor() as isize);
+
+            if tile_type != 0 {
+                vert = Raycast {
+                    x: rayx,
+                    y: rayy,
+                    tile_type,
+                    vertical: true,
+                    u: rayy.fract(),
+                };
+                break;
+            }
+
+            rayx -= 1.0;
+            rayy -= angle.tan();
+        }
+    }
+
+    let mut horiz = Raycast {
+        x: 0.0,
+        y: 0.0,
+        tile_type: 0,
+        vertical: false,
+        u: 0.0,
+    };
+
+    //Check horizontal lines
+    if angle.sin() > 0.0 {
+        let mut rayy = starty.ceil();
+        let mut rayx = (rayy - starty) * 1.0 / angle.tan() + startx;
+        while (starty - rayy).abs() < max_dist {
+            let tile_type = level.get_tile(rayx.floor() as isize, rayy as isize);
+
+            if tile_type != 0 {
+                horiz = Raycast {
+                    x: rayx,
+                    y: rayy,
+                    tile_type,
+                    vertical: false,
+                    u: rayx.fract(),
+                };
+                break;
+            }
+
+            rayy += 1.0;
+            rayx += 1.0 / angle.tan();
+        }
+    } else if angle.sin() < 0.0 {
+        let mut rayy = starty.floor();
+        let mut rayx = (rayy - starty) * 1.0 / angle.tan() + startx;
+        while (starty - rayy).abs() < max_dist {
+            let tile_type = level.get_tile(rayx.floor() as isize, rayy as isize - 1);
+
+            if tile_type != 0 {
+                horiz = Raycast {
+                    x: rayx,
+                    y: rayy,
+                    tile_type,
+                    vertical: false,
+                    u: rayx.fract(),
+                };
+                break;
+            }
+
+            rayy -= 1.0;
+            rayx -= 1.0 / angle.tan();
+        }
+    }
+
+    //Return the value that is closest
+    let mut nearest = if (dist(horiz.x, horiz.y, startx, starty)
+        < dist(vert.x, vert.y, startx, starty)
+        && horiz.tile_type != 0)
+        || vert.tile_type == 0
+    {
+        horiz
+    } else {
+        vert
+    };
+
+    //Moving push walls are not in the grid, they are checked one by one
+    let mut nearest_dist = if nearest.tile_type != 0 {
+        dist(nearest.x, nearest.y, startx, starty)
+    } else {
+        max_dist
+    };
+    for pushwall in &level.pushwalls {
+        if let Some((left, top)) = pushwall.block() {
+            if let Some((block_dist, ray)) =
+                hit_block(startx, starty, angle, left, top, pushwall.tile)
+            {
+                if block_dist < nearest_dist {
+                    nearest = ray;
+                    nearest_dist = block_dist;
+                }
+            }
+        }
+    }
+    nearest
+}
+
+//Where a ray from (startx, starty) towards `angle` hits the one cell block
+//with its top left corner at (left, top), and how far away that is
+fn hit_block(
+    startx: f64,
+    starty: f64,
+    angle: f64,
+    left: f64,
+    top: f64,
+    tile_type: u8,
+) -> Option<(f64, Raycast)> {
+    //Distances along the ray at which it enters and leaves the block along
+    //one axis
+    let slab = |start: f64, low: f64, dir: f64| {
+        if dir == 0.0 {
+            (low..low + 1.0)
+                .contains(&start)
+                .then_some((f64::NEG_INFINITY, f64::INFINITY))
+        } else {
+            let (a, b) = ((low - start) / dir, (low + 1.0 - start) / dir);
+            Some((a.min(b), a.max(b)))
+        }
+    };
+
+    let (sin, cos) = angle.sin_cos();
+    let (xnear, xfar) = slab(startx, left, cos)?;
+    let (ynear, yfar) = slab(starty, top, sin)?;
+    let (near, far) = (xnear.max(ynear), xfar.min(yfar));
+    if near < 0.0 || near > far {
+        return None;
+    }
+
+    let (x, y) = (startx + cos * near, starty + sin * near);
+    let vertical = xnear > ynear;
+    Some((
+        near,
+        Raycast {
+            x,
+            y,
+            tile_type,
+            vertical,
+            u: if vertical { y - top } else { x - left },
+        },
+    ))
+}
+
+//True if moving `dist` from (x, y) towards `angle` does not run into a wall,
+//a negative distance moves backwards. Everything that walks around the level
+//uses this so it collides with the grid the same way.
+pub fn can_move(level: &Level, x: f64, y: f64, angle: f64, dist: f64) -> bool {
+    if dist < 0.0 {
+        raycast(level, x, y, angle + PI, -dist).tile_type == 0
+    } else {
+        raycast(level, x, y, angle, dist).tile_type == 0
+    }
+}
+
+//True if no wall is in the way between (x1, y1) and (x2, y2)
+pub fn line_of_sight(level: &Level, x1: f64, y1: f64, x2: f64, y2: f64) -> bool {
+    let target = dist(x1, y1, x2, y2);
+    let ray = raycast(level, x1, y1, (y2 - y1).atan2(x2 - x1), target);
+    ray.tile_type == 0 || dist(x1, y1, ray.x, ray.y) >= target
+}
@@ -0,0 +1,96 @@
+use crate::error::Error;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+//Height of a sprite on screen in pixels at distance 1
+const SPRITE_SIZE: f64 = 400.0;
+//Height of a sprite in world units
+pub const SPRITE_HEIGHT: f64 = SPRITE_SIZE / 600.0;
+
+//Width of a sprite in world units, the screen is stretched sideways with the
+//field of view so this depends on it
+pub fn sprite_width(fov: f64) -> f64 {
+    SPRITE_SIZE / 800.0 * fov
+}
+
+//Camera state needed to project sprites onto the screen
+pub struct View {
+    pub x: f64,
+    pub y: f64,
+    pub rotation: f64,
+    //Height of the camera above the floor
+    pub z: f64,
+    //Screen row of the horizon
+    pub horizon: f64,
+    pub fov: f64,
+}
+
+impl View {
+    //Position of (x, y) relative to the camera: sideways and forward distance
+    pub fn transform(&self, x: f64, y: f64) -> (f64, f64) {
+        let trans_x = x - self.x;
+        let trans_y = y - self.y;
+        let rotated_y = trans_x * (-self.rotation).cos() - trans_y * (-self.rotation).sin();
+        let rotated_x = trans_x * (-self.rotation).sin() + trans_y * (-self.rotation).cos();
+        (rotated_x, rotated_y)
+    }
+}
+
+//Draws a 64x64 sprite standing on the floor at (x, y), columns that are behind
+//a wall in `depthbuffer` are skipped. `frame` picks the frame from a sprite
+//sheet of 64x64 frames side by side.
+pub fn draw_sprite(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    view: &View,
+    depthbuffer: &[f64],
+    x: f64,
+    y: f64,
+    frame: u32,
+) -> Result<(), Error> {
+    let (sprite_rotated_x, sprite_rotated_y) = view.transform(x, y);
+
+    let sprite_sz = SPRITE_SIZE;
+
+    let sprite_screen_size = (sprite_sz / sprite_rotated_y) as u32;
+    //The bottom of the sprite rests on the floor
+    let sprite_screen_y =
+        (view.z * 600.0 / sprite_rotated_y + view.horizon - sprite_screen_size as f64 / 2.0) as i32;
+    let norm_x = (sprite_rotated_x / sprite_rotated_y).atan() / view.fov + 0.5;
+    let sprite_screen_x = (norm_x * 800.0) as i32;
+
+    let fov_range = 2.0 * (view.fov / 2.0).tan() * sprite_rotated_y;
+    let sprite_start_x = ((sprite_rotated_x - sprite_sz / 600.0) / fov_range) + 0.5;
+    let sprite_end_x = ((sprite_rotated_x + sprite_sz / 600.0) / fov_range) + 0.5;
+
+    if ((sprite_start_x < 1.0 && sprite_end_x > 0.0)
+        || (sprite_end_x > 1.0 && sprite_start_x < 0.0))
+        && sprite_rotated_y > 0.0
+    {
+        let startx = (sprite_screen_x - sprite_screen_size as i32 / 2) / 4;
+        let endx = (sprite_screen_x + sprite_screen_size as i32 / 2) / 4;
+        let mut pixel_x = 0.0f64;
+        for i in startx..endx {
+            if i >= 0
+                && (i as usize) < depthbuffer.len()
+                && depthbuffer[i as usize] > sprite_rotated_y
+            {
+                canvas
+                    .copy(
+                        texture,
+                        Rect::new(frame as i32 * 64 + (pixel_x as i32).min(63), 0, 1, 64),
+                        Rect::from_center(
+                            Point::new(i * 4 + 2, sprite_screen_y),
+                            4,
+                            sprite_screen_size,
+                        ),
+                    )
+                    .map_err(Error::Sdl)?;
+            }
+            pixel_x += 64.0 / sprite_screen_size as f64 * 4.0;
+        }
+    }
+
+    Ok(())
+}
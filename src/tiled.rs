@@ -0,0 +1,421 @@
+use crate::error::Error;
+use crate::level::{Level, LevelSprite, Light, PlayerStart};
+use base64::Engine;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+//The top bits of a global tile id say how the tile is flipped or rotated
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+//Lights without a radius property light this many cells around them
+const DEFAULT_LIGHT_RADIUS: f64 = 3.0;
+
+//A tile layer or object read from either file format, before it is turned
+//into a level
+struct TileLayer {
+    name: String,
+    gids: Vec<u32>,
+}
+
+struct Object {
+    name: String,
+    kind: String,
+    //Center of the object in pixels
+    x: f64,
+    y: f64,
+    width: f64,
+    properties: Vec<(String, String)>,
+}
+
+impl Object {
+    fn property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn number(&self, name: &str, default: f64) -> Result<f64, String> {
+        match self.property(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("object {}: invalid {name}: {value}", self.name)),
+            None => Ok(default),
+        }
+    }
+}
+
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_width: f64,
+    tile_height: f64,
+    //First global id of the first tileset, it becomes tile 1
+    first_gid: u32,
+    layers: Vec<TileLayer>,
+    objects: Vec<Object>,
+}
+
+//Whether `path` looks like a map saved by the Tiled editor rather than a
+//level file
+pub fn is_tiled_map(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("tmx" | "tmj" | "json")
+    )
+}
+
+//Loads a map saved by the Tiled editor, see `parse`
+pub fn load(path: &Path) -> Result<Level, Error> {
+    let text = fs::read_to_string(path).map_err(|source| Error::AssetNotFound {
+        path: PathBuf::from(path),
+        source,
+    })?;
+
+    parse(&text, path)
+}
+
+//Parses a Tiled map, .json and .tmj files are read as JSON and anything else
+//as TMX, `path` is only used for that and for error messages. Tile layers
+//named walls, floor and ceiling become the level layers (the last one if
+//several share a name), tile ids count from 1 in the order of the first
+//tileset so it should match textures.png. Objects of type "start" (with an
+//optional "angle" property in degrees) set the player start, objects of type
+//"light" (with optional "radius" in cells and "intensity" properties) become
+//lights, objects without a type are skipped and any other object becomes a
+//sprite of that type.
+pub fn parse(text: &str, path: &Path) -> Result<Level, Error> {
+    let is_json = matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("json" | "tmj")
+    );
+    let map = if is_json {
+        parse_json(text)
+    } else {
+        parse_tmx(text)
+    };
+
+    map.and_then(|map| build_level(&map))
+        .map_err(|msg| Error::InvalidLevel(format!("{}: {msg}", path.display())))
+}
+
+//Decodes the tile ids of a layer stored as CSV or as base64 little endian
+//u32s, optionally zlib or gzip compressed
+fn decode_data(
+    data: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> Result<Vec<u32>, String> {
+    match encoding {
+        Some("csv") => data
+            .split(',')
+            .map(|gid| gid.trim())
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| format!("invalid tile id: {gid}")))
+            .collect(),
+        Some("base64") => {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(data.trim())
+                .map_err(|err| format!("invalid base64 data: {err}"))?;
+            let mut raw = Vec::new();
+            match compression {
+                None | Some("") => raw = bytes,
+                Some("zlib") => {
+                    ZlibDecoder::new(bytes.as_slice())
+                        .read_to_end(&mut raw)
+                        .map_err(|err| format!("invalid zlib data: {err}"))?;
+                }
+                Some("gzip") => {
+                    GzDecoder::new(bytes.as_slice())
+                        .read_to_end(&mut raw)
+                        .map_err(|err| format!("invalid gzip data: {err}"))?;
+                }
+                Some(other) => return Err(format!("unsupported compression: {other}")),
+            }
+
+            if raw.len() % 4 != 0 {
+                return Err(String::from("layer data is not a whole number of tiles"));
+            }
+            Ok(raw
+                .chunks_exact(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect())
+        }
+        Some(other) => Err(format!("unsupported encoding: {other}")),
+        None => Err(String::from(
+            "XML tile data is not supported, save the map with CSV or base64 layers",
+        )),
+    }
+}
+
+fn parse_tmx(text: &str) -> Result<TiledMap, String> {
+    let doc = roxmltree::Document::parse(text).map_err(|err| err.to_string())?;
+    let root = doc.root_element();
+    if !root.has_tag_name("map") {
+        return Err(String::from("not a TMX map"));
+    }
+
+    let attribute = |node: roxmltree::Node, name: &str| -> Result<f64, String> {
+        let value = node
+            .attribute(name)
+            .ok_or_else(|| format!("<{}> has no {name}", node.tag_name().name()))?;
+        value
+            .parse()
+            .map_err(|_| format!("invalid {name}: {value}"))
+    };
+
+    let mut map = TiledMap {
+        width: attribute(root, "width")? as usize,
+        height: attribute(root, "height")? as usize,
+        tile_width: attribute(root, "tilewidth")?,
+        tile_height: attribute(root, "tileheight")?,
+        first_gid: 1,
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+
+    if let Some(tileset) = root.children().find(|node| node.has_tag_name("tileset")) {
+        map.first_gid = attribute(tileset, "firstgid")? as u32;
+    }
+
+    //Layers can be nested inside groups
+    for node in root.descendants() {
+        if node.has_tag_name("layer") {
+            let data = node
+                .children()
+                .find(|node| node.has_tag_name("data"))
+                .ok_or_else(|| String::from("<layer> has no <data>"))?;
+            let name = node.attribute("name").unwrap_or_default();
+            let gids = decode_data(
+                data.text().unwrap_or_default(),
+                data.attribute("encoding"),
+                data.attribute("compression"),
+            )
+            .map_err(|err| format!("layer {name}: {err}"))?;
+            map.layers.push(TileLayer {
+                name: name.to_string(),
+                gids,
+            });
+        } else if node.has_tag_name("object") {
+            let properties = node
+                .descendants()
+                .filter(|node| node.has_tag_name("property"))
+                .map(|property| {
+                    (
+                        property.attribute("name").unwrap_or_default().to_string(),
+                        property
+                            .attribute("value")
+                            .or_else(|| property.text())
+                            .unwrap_or_default()
+                            .to_string(),
+                    )
+                })
+                .collect();
+            let width = attribute(node, "width").unwrap_or(0.0);
+            let height = attribute(node, "height").unwrap_or(0.0);
+            //Tile objects are anchored at their bottom left corner
+            let top = if node.has_attribute("gid") {
+                attribute(node, "y")? - height
+            } else {
+                attribute(node, "y")?
+            };
+            map.objects.push(Object {
+                name: node.attribute("name").unwrap_or_default().to_string(),
+                kind: node
+                    .attribute("type")
+                    .or_else(|| node.attribute("class"))
+                    .unwrap_or_default()
+                    .to_string(),
+                x: attribute(node, "x")? + width / 2.0,
+                y: top + height / 2.0,
+                width,
+                properties,
+            });
+        }
+    }
+
+    Ok(map)
+}
+
+fn parse_json(text: &str) -> Result<TiledMap, String> {
+    let root: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+
+    let number = |value: &Value, name: &str| -> Result<f64, String> {
+        value[name]
+            .as_f64()
+            .ok_or_else(|| format!("missing or invalid {name}"))
+    };
+    let string = |value: &Value, name: &str| value[name].as_str().unwrap_or_default().to_string();
+
+    let mut map = TiledMap {
+        width: number(&root, "width")? as usize,
+        height: number(&root, "height")? as usize,
+        tile_width: number(&root, "tilewidth")?,
+        tile_height: number(&root, "tileheight")?,
+        first_gid: root["tilesets"][0]["firstgid"].as_u64().unwrap_or(1) as u32,
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+
+    //Layers can be nested inside groups, they are flattened in file order
+    fn flatten<'v>(layers: &'v Value, flat: &mut Vec<&'v Value>) {
+        for layer in layers.as_array().into_iter().flatten() {
+            if layer["type"].as_str() == Some("group") {
+                flatten(&layer["layers"], flat);
+            } else {
+                flat.push(layer);
+            }
+        }
+    }
+    let mut layers = Vec::new();
+    flatten(&root["layers"], &mut layers);
+
+    for layer in layers {
+        let name = string(layer, "name");
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let gids = match &layer["data"] {
+                    Value::Array(gids) => gids
+                        .iter()
+                        .map(|gid| {
+                            gid.as_u64()
+                                .map(|gid| gid as u32)
+                                .ok_or_else(|| format!("layer {name}: invalid tile id: {gid}"))
+                        })
+                        .collect::<Result<Vec<u32>, String>>()?,
+                    Value::String(data) => decode_data(
+                        data,
+                        layer["encoding"].as_str(),
+                        layer["compression"].as_str(),
+                    )
+                    .map_err(|err| format!("layer {name}: {err}"))?,
+                    _ => return Err(format!("layer {name} has no data")),
+                };
+                map.layers.push(TileLayer { name, gids });
+            }
+            Some("objectgroup") => {
+                for object in layer["objects"].as_array().into_iter().flatten() {
+                    let properties = object["properties"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|property| {
+                            let value = match &property["value"] {
+                                Value::String(value) => value.clone(),
+                                value => value.to_string(),
+                            };
+                            (string(property, "name"), value)
+                        })
+                        .collect();
+                    let width = object["width"].as_f64().unwrap_or(0.0);
+                    let height = object["height"].as_f64().unwrap_or(0.0);
+                    //Tile objects are anchored at their bottom left corner
+                    let top = if object.get("gid").is_some() {
+                        number(object, "y")? - height
+                    } else {
+                        number(object, "y")?
+                    };
+                    let mut kind = string(object, "type");
+                    if kind.is_empty() {
+                        kind = string(object, "class");
+                    }
+                    map.objects.push(Object {
+                        name: string(object, "name"),
+                        kind,
+                        x: number(object, "x")? + width / 2.0,
+                        y: top + height / 2.0,
+                        width,
+                        properties,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(map)
+}
+
+fn build_level(map: &TiledMap) -> Result<Level, String> {
+    let size = map
+        .width
+        .checked_mul(map.height)
+        .ok_or_else(|| format!("map size {}x{} is too large", map.width, map.height))?;
+    let layer = |name: &str| -> Result<Option<Vec<u8>>, String> {
+        //The last layer with the name wins, like it is drawn on top in Tiled
+        let layer = match map.layers.iter().rev().find(|layer| layer.name == name) {
+            Some(layer) => layer,
+            None => return Ok(None),
+        };
+        if layer.gids.len() != size {
+            return Err(format!(
+                "layer {name} has {} tiles, expected {size}",
+                layer.gids.len()
+            ));
+        }
+
+        layer
+            .gids
+            .iter()
+            .map(|&gid| {
+                let gid = gid & !FLIP_FLAGS;
+                if gid == 0 {
+                    return Ok(0);
+                }
+                gid.checked_sub(map.first_gid)
+                    .and_then(|tile| u8::try_from(tile + 1).ok())
+                    .ok_or_else(|| format!("layer {name}: tile id {gid} is out of range"))
+            })
+            .collect::<Result<Vec<u8>, String>>()
+            .map(Some)
+    };
+
+    let walls = layer("walls")?.ok_or_else(|| String::from("there is no walls layer"))?;
+    let floor = layer("floor")?.unwrap_or_else(|| vec![0; size]);
+    let ceiling = layer("ceiling")?.unwrap_or_else(|| vec![0; size]);
+    let mut level =
+        Level::new(map.width, map.height, walls, floor, ceiling).map_err(|err| match err {
+            Error::InvalidLevel(msg) => msg,
+            err => err.to_string(),
+        })?;
+
+    for object in &map.objects {
+        let x = object.x / map.tile_width;
+        let y = object.y / map.tile_height;
+        match object.kind.as_str() {
+            "start" | "player_start" => {
+                level.start = PlayerStart {
+                    x,
+                    y,
+                    angle: object.number("angle", 0.0)?.to_radians(),
+                };
+            }
+            "light" => {
+                let radius = if object.width > 0.0 {
+                    object.width / map.tile_width / 2.0
+                } else {
+                    DEFAULT_LIGHT_RADIUS
+                };
+                level.lights.push(Light {
+                    x,
+                    y,
+                    radius: object.number("radius", radius)?,
+                    intensity: object.number("intensity", 1.0)?,
+                });
+            }
+            //Objects without a type are notes and the like, not part of the
+            //level
+            "" => {}
+            kind => level.sprites.push(LevelSprite {
+                kind: kind.to_string(),
+                x,
+                y,
+            }),
+        }
+    }
+
+    Ok(level)
+}
@@ -0,0 +1,114 @@
+use crate::error::Error;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use std::f64::consts::PI;
+
+//Each frame of weapon.png is drawn this many times bigger
+const SCALE: u32 = 4;
+//Length of the firing animation
+const FIRE_TIME: f64 = 0.1;
+//Steps per second at full walking speed, two steps per bob cycle
+const STEP_RATE: f64 = 2.0;
+//Walking speed at which the bob is strongest
+const FULL_BOB_SPEED: f64 = 2.0;
+//Largest sideways and vertical bob in screen pixels
+const BOB_X: f64 = 16.0;
+const BOB_Y: f64 = 12.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Fire,
+    Reload,
+}
+
+//The weapon drawn over the 3D view. weapon.png has a row of 64x64 frames for
+//each weapon: idle, two firing frames and two reload frames.
+pub struct ViewModel {
+    pub state: State,
+    state_time: f64,
+    //Length of the reload animation of the weapon that was fired last
+    reload_time: f64,
+    //Position in the bob cycle and how strong the bob is, 0 when standing
+    //still and 1 at full speed
+    bob_phase: f64,
+    bob_amount: f64,
+}
+
+impl ViewModel {
+    pub fn new() -> ViewModel {
+        ViewModel {
+            state: State::Idle,
+            state_time: 0.0,
+            reload_time: 0.0,
+            bob_phase: 0.0,
+            bob_amount: 0.0,
+        }
+    }
+
+    //Starts the firing animation, followed by `reload_time` seconds of reload
+    //animation
+    pub fn fire(&mut self, reload_time: f64) {
+        self.state = State::Fire;
+        self.state_time = 0.0;
+        self.reload_time = reload_time;
+    }
+
+    //`speed` is how fast the player is walking
+    pub fn update(&mut self, dt: f64, speed: f64) {
+        self.state_time += dt;
+        if self.state == State::Fire && self.state_time >= FIRE_TIME {
+            self.state = if self.reload_time > 0.0 {
+                State::Reload
+            } else {
+                State::Idle
+            };
+            self.state_time = 0.0;
+        }
+        if self.state == State::Reload && self.state_time >= self.reload_time {
+            self.state = State::Idle;
+            self.state_time = 0.0;
+        }
+
+        //Ease in and out of the bob so stopping does not snap the weapon back
+        let target = (speed.abs() / FULL_BOB_SPEED).min(1.0);
+        self.bob_amount += (target - self.bob_amount) * (dt * 8.0).min(1.0);
+        self.bob_phase = (self.bob_phase + dt * STEP_RATE * PI * self.bob_amount) % (PI * 2.0);
+    }
+
+    pub fn frame(&self) -> u32 {
+        match self.state {
+            State::Idle => 0,
+            State::Fire => 1 + (self.state_time * 2.0 / FIRE_TIME).min(1.0) as u32,
+            State::Reload => 3 + (self.state_time * 2.0 / self.reload_time).min(1.0) as u32,
+        }
+    }
+
+    //Draws `weapon` (the row in weapon.png) at the bottom center of the screen
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture: &Texture,
+        weapon: usize,
+    ) -> Result<(), Error> {
+        let size = 64 * SCALE;
+        //The weapon sways from side to side once per bob cycle and dips with
+        //every step
+        let bobx = self.bob_phase.cos() * BOB_X * self.bob_amount;
+        let boby = self.bob_phase.sin().abs() * BOB_Y * self.bob_amount;
+        canvas
+            .copy(
+                texture,
+                Rect::new(self.frame() as i32 * 64, weapon as i32 * 64, 64, 64),
+                Rect::new(
+                    (800 - size as i32) / 2 + bobx as i32,
+                    600 - size as i32 + boby as i32 + SCALE as i32 * 2,
+                    size,
+                    size,
+                ),
+            )
+            .map_err(Error::Sdl)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::bitmap::BitMap;
+use crate::level::Level;
+use crate::player::AmmoKind;
+use crate::projectile::ProjectileKind;
+use crate::raycast::{raycast, Raycast};
+use crate::sprite;
+
+pub struct WeaponKind {
+    pub name: &'static str,
+    pub damage: i32,
+    //Seconds between shots
+    pub delay: f64,
+    //Length of the reload animation after each shot, shorter than `delay`
+    pub reload: f64,
+    pub range: f64,
+    //Keeps firing while the trigger is held
+    pub automatic: bool,
+    //Every shot uses up one round of this
+    pub ammo: AmmoKind,
+    //Fires projectiles of this kind instead of hitting instantly, `damage`
+    //and `range` are not used then
+    pub projectile: Option<ProjectileKind>,
+}
+
+pub const WEAPONS: [WeaponKind; 3] = [
+    WeaponKind {
+        name: "PISTOL",
+        damage: 12,
+        delay: 0.4,
+        reload: 0.25,
+        range: 32.0,
+        automatic: false,
+        ammo: AmmoKind::Bullets,
+        projectile: None,
+    },
+    WeaponKind {
+        name: "MACHINE GUN",
+        damage: 7,
+        delay: 0.12,
+        reload: 0.0,
+        range: 24.0,
+        automatic: true,
+        ammo: AmmoKind::Bullets,
+        projectile: None,
+    },
+    WeaponKind {
+        name: "ROCKET LAUNCHER",
+        damage: 0,
+        delay: 0.9,
+        reload: 0.6,
+        range: 0.0,
+        automatic: false,
+        ammo: AmmoKind::Rockets,
+        projectile: Some(ProjectileKind::Rocket),
+    },
+];
+
+//The weapon in hand and its trigger
+pub struct Weapons {
+    //Index into WEAPONS
+    pub current: usize,
+    pub trigger: bool,
+    //Time until the next shot can be fired
+    cooldown: f64,
+    //A weapon that is not automatic fires once each time the trigger is
+    //pulled
+    fired: bool,
+}
+
+impl Weapons {
+    pub fn new() -> Weapons {
+        Weapons {
+            current: 0,
+            trigger: false,
+            cooldown: 0.0,
+            fired: false,
+        }
+    }
+
+    pub fn weapon(&self) -> &'static WeaponKind {
+        &WEAPONS[self.current]
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < WEAPONS.len() && index != self.current {
+            self.current = index;
+            self.cooldown = self.cooldown.max(0.2);
+        }
+    }
+
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+        if !pulled {
+            self.fired = false;
+        }
+    }
+
+    //Advances by `dt` seconds, returns true if a shot is fired. Nothing is
+    //fired without `has_ammo`.
+    pub fn update(&mut self, dt: f64, has_ammo: bool) -> bool {
+        self.cooldown = (self.cooldown - dt).max(0.0);
+        if !self.trigger
+            || !has_ammo
+            || self.cooldown > 0.0
+            || (self.fired && !self.weapon().automatic)
+        {
+            return false;
+        }
+
+        self.cooldown = self.weapon().delay;
+        self.fired = true;
+        true
+    }
+}
+
+//Something a shot can hit besides walls: a sprite standing at (x, y) drawn
+//with `frame` of `sheet`, only its opaque texels can be hit
+pub struct Target<'a> {
+    pub x: f64,
+    pub y: f64,
+    pub sheet: &'a BitMap,
+    pub frame: u32,
+}
+
+//A shot from (x, y) at height `z` above the floor towards `angle`, rising by
+//`slope` per unit of distance
+pub struct Shot {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub angle: f64,
+    pub slope: f64,
+    pub range: f64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Hit {
+    //Nothing within range
+    Nothing,
+    //A wall was hit where `ray` ended, `z` is the height of the impact
+    Wall { ray: Raycast, z: f64 },
+    //`index` is the position of the target in the list passed to `hitscan`
+    Target { index: usize, dist: f64 },
+}
+
+//Traces `shot` against the walls of the level and the targets, returning the
+//nearest hit. Targets are billboards facing the shot like they face the
+//camera when drawn, `fov` is needed for their width.
+pub fn hitscan(level: &Level, targets: &[Target], shot: &Shot, fov: f64) -> Hit {
+    let (sin, cos) = shot.angle.sin_cos();
+    let ray = raycast(level, shot.x, shot.y, shot.angle, shot.range);
+    let wall_dist = if ray.tile_type != 0 {
+        (ray.x - shot.x) * cos + (ray.y - shot.y) * sin
+    } else {
+        f64::INFINITY
+    };
+
+    let width = sprite::sprite_width(fov);
+    let mut nearest: Option<(usize, f64)> = None;
+    for (index, target) in targets.iter().enumerate() {
+        let (dx, dy) = (target.x - shot.x, target.y - shot.y);
+        //Distance along the shot and to the side of it
+        let dist = dx * cos + dy * sin;
+        let side = dy * cos - dx * sin;
+        if dist <= 0.0 || dist >= wall_dist || dist > shot.range {
+            continue;
+        }
+        if nearest.is_some_and(|(_, nearest_dist)| nearest_dist <= dist) {
+            continue;
+        }
+
+        let u = side / width + 0.5;
+        let v = 1.0 - (shot.z + shot.slope * dist) / sprite::SPRITE_HEIGHT;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            continue;
+        }
+        let (texelx, texely) = (
+            target.frame as usize * 64 + (u * 64.0) as usize,
+            (v * 64.0) as usize,
+        );
+        if texelx < target.sheet.width
+            && texely < target.sheet.height
+            && target.sheet.pixel(texelx, texely)[3] > 0
+        {
+            nearest = Some((index, dist));
+        }
+    }
+
+    match nearest {
+        Some((index, dist)) => Hit::Target { index, dist },
+        None if ray.tile_type != 0 => Hit::Wall {
+            ray,
+            //Floors and ceilings do not stop shots, steep shots hit the
+            //wall at its bottom or top
+            z: (shot.z + shot.slope * wall_dist).clamp(0.0, 1.0),
+        },
+        None => Hit::Nothing,
+    }
+}
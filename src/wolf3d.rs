@@ -0,0 +1,444 @@
+use crate::error::Error;
+use crate::level::{Key, Level, LevelSprite, PlayerStart};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//MAPHEAD has room for this many map offsets
+const MAX_MAPS: usize = 100;
+//Plane offsets and lengths, width, height and name. Wolfenstein 3D maps are
+//always 64x64 but the header stores the size anyway.
+const MAP_HEADER_SIZE: usize = 38;
+
+//Carmack compression markers, the high byte of a word
+const NEAR_POINTER: u8 = 0xa7;
+const FAR_POINTER: u8 = 0xa8;
+
+//Wall tile used for every door, the importer only has the four textures of
+//textures.png to pick from
+const DOOR_TILE: u8 = 4;
+const FLOOR_TILE: u8 = 2;
+const CEILING_TILE: u8 = 1;
+//Plane 1 object that turns the wall under it into a push wall
+const PUSHWALL_OBJECT: u16 = 98;
+
+//Little endian reader over a byte slice, running past the end is an error
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| format!("unexpected end of data at offset {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+//Expands Carmack compressed data. The first word is the expanded size in
+//bytes, a word with NEAR_POINTER or FAR_POINTER as its high byte copies
+//`low byte` words from earlier in the output: near pointers are followed by a
+//byte counting words back from the end, far pointers by a word offset from the
+//start. A count of 0 escapes a literal word whose high byte is the marker.
+pub fn carmack_expand(data: &[u8]) -> Result<Vec<u16>, String> {
+    let mut reader = Reader::new(data, 0);
+    let len = reader.u16()? as usize / 2;
+    let mut out = Vec::with_capacity(len);
+
+    while out.len() < len {
+        let word = reader.u16()?;
+        let [count, marker] = word.to_le_bytes();
+        if marker != NEAR_POINTER && marker != FAR_POINTER {
+            out.push(word);
+            continue;
+        }
+
+        if count == 0 {
+            out.push(u16::from_le_bytes([reader.u8()?, marker]));
+            continue;
+        }
+
+        let start = if marker == NEAR_POINTER {
+            let back = reader.u8()? as usize;
+            out.len()
+                .checked_sub(back)
+                .ok_or_else(|| format!("near pointer {back} words before the start"))?
+        } else {
+            reader.u16()? as usize
+        };
+
+        //Copies may overlap the words being written, so go one word at a time
+        for i in start..start + count as usize {
+            let word = *out
+                .get(i)
+                .ok_or_else(|| format!("pointer to word {i} past the end of the output"))?;
+            out.push(word);
+        }
+    }
+
+    out.truncate(len);
+    Ok(out)
+}
+
+//Expands RLEW compressed words. The first word is the expanded size in bytes,
+//`tag` followed by a count and a value repeats the value count times.
+pub fn rlew_expand(data: &[u16], tag: u16) -> Result<Vec<u16>, String> {
+    let mut words = data.iter().copied();
+    let mut next = || {
+        words
+            .next()
+            .ok_or_else(|| String::from("unexpected end of data"))
+    };
+
+    let len = next()? as usize / 2;
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len {
+        let word = next()?;
+        if word == tag {
+            let count = next()? as usize;
+            let value = next()?;
+            out.extend(std::iter::repeat_n(value, count));
+        } else {
+            out.push(word);
+        }
+    }
+
+    out.truncate(len);
+    Ok(out)
+}
+
+//A decompressed map from GAMEMAPS
+pub struct Map {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    //Walls, doors and floor areas
+    pub plane0: Vec<u16>,
+    //Objects: player start, items and enemies
+    pub plane1: Vec<u16>,
+}
+
+//Reads map number `map` from the contents of MAPHEAD and GAMEMAPS
+pub fn read_map(maphead: &[u8], gamemaps: &[u8], map: usize) -> Result<Map, String> {
+    if map >= MAX_MAPS {
+        return Err(format!(
+            "map {map} is out of range, there are at most {MAX_MAPS}"
+        ));
+    }
+
+    let mut head = Reader::new(maphead, 0);
+    let tag = head.u16().map_err(|err| format!("MAPHEAD: {err}"))?;
+    head.pos += map * 4;
+    let offset = head.i32().map_err(|err| format!("MAPHEAD: {err}"))?;
+    if offset <= 0 {
+        return Err(format!("map {map} does not exist"));
+    }
+    if offset as usize + MAP_HEADER_SIZE > gamemaps.len() {
+        return Err(format!("map {map} header is past the end of GAMEMAPS"));
+    }
+
+    let mut header = Reader::new(gamemaps, offset as usize);
+    let mut plane_offsets = [0i32; 3];
+    for plane_offset in &mut plane_offsets {
+        *plane_offset = header.i32()?;
+    }
+    let mut plane_lengths = [0u16; 3];
+    for plane_length in &mut plane_lengths {
+        *plane_length = header.u16()?;
+    }
+    let width = header.u16()? as usize;
+    let height = header.u16()? as usize;
+    let name = header.bytes(16)?;
+    let name = String::from_utf8_lossy(name.split(|&b| b == 0).next().unwrap_or(&[])).into_owned();
+
+    let plane = |plane: usize| -> Result<Vec<u16>, String> {
+        let start = plane_offsets[plane].max(0) as usize;
+        let data = gamemaps
+            .get(start..start + plane_lengths[plane] as usize)
+            .ok_or_else(|| format!("map {map} plane {plane} is past the end of GAMEMAPS"))?;
+        let words = carmack_expand(data)
+            .and_then(|words| rlew_expand(&words, tag))
+            .map_err(|err| format!("map {map} plane {plane}: {err}"))?;
+        if words.len() != width * height {
+            return Err(format!(
+                "map {map} plane {plane} has {} tiles, expected {}",
+                words.len(),
+                width * height
+            ));
+        }
+        Ok(words)
+    };
+
+    Ok(Map {
+        name,
+        width,
+        height,
+        plane0: plane(0)?,
+        plane1: plane(1)?,
+    })
+}
+
+//What a plane 1 object becomes, None for objects the demo has no use for
+fn sprite_kind(object: u16) -> Option<&'static str> {
+    match object {
+        43 => Some("key_gold"),
+        44 => Some("key_silver"),
+        47 | 48 => Some("health"),
+        49 => Some("ammo"),
+        23..=70 => Some("decoration"),
+        108..=227 => Some("enemy"),
+        _ => None,
+    }
+}
+
+//Converts a Wolfenstein 3D map into a level. Solid walls cycle through the
+//available wall textures, every door becomes a door on DOOR_TILE and secret
+//walls become push walls.
+pub fn convert_map(map: &Map) -> Result<Level, Error> {
+    let walls = map
+        .plane0
+        .iter()
+        .map(|&tile| match tile {
+            1..=63 => ((tile - 1) % 4) as u8 + 1,
+            90..=101 => DOOR_TILE,
+            _ => 0,
+        })
+        .collect();
+    let size = map.width * map.height;
+    let mut level = Level::new(
+        map.width,
+        map.height,
+        walls,
+        vec![FLOOR_TILE; size],
+        vec![CEILING_TILE; size],
+    )?;
+
+    for (ind, &tile) in map.plane0.iter().enumerate() {
+        //Doors 92 to 95 need the gold or silver key
+        let lock = match tile {
+            92 | 93 => Some(Key::Gold),
+            94 | 95 => Some(Key::Silver),
+            _ => None,
+        };
+        if (90..=101).contains(&tile) {
+            level.add_door(ind % map.width, ind / map.width, lock)?;
+        }
+    }
+
+    for (ind, &object) in map.plane1.iter().enumerate() {
+        let x = (ind % map.width) as f64 + 0.5;
+        let y = (ind / map.width) as f64 + 0.5;
+        //Wolfenstein 3D has north at the top of the map, which is -y here
+        let angle = match object {
+            19 => Some(PI * 1.5),
+            20 => Some(0.0),
+            21 => Some(PI * 0.5),
+            22 => Some(PI),
+            _ => None,
+        };
+        if let Some(angle) = angle {
+            level.start = PlayerStart { x, y, angle };
+        } else if object == PUSHWALL_OBJECT {
+            //Markers that are not on a wall are left out
+            let _ = level.add_pushwall(ind % map.width, ind / map.width);
+        } else if let Some(kind) = sprite_kind(object) {
+            level.sprites.push(LevelSprite {
+                kind: String::from(kind),
+                x,
+                y,
+            });
+        }
+    }
+
+    Ok(level)
+}
+
+//Loads map number `map` (counting from 0) from a MAPHEAD and GAMEMAPS file
+//pair, for example MAPHEAD.WL1 and GAMEMAPS.WL1 from the shareware episode
+pub fn load(maphead: &Path, gamemaps: &Path, map: usize) -> Result<Level, Error> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|source| Error::AssetNotFound {
+            path: PathBuf::from(path),
+            source,
+        })
+    };
+    let maphead_data = read(maphead)?;
+    let gamemaps_data = read(gamemaps)?;
+
+    let map = read_map(&maphead_data, &gamemaps_data, map)
+        .map_err(|msg| Error::InvalidLevel(format!("{}: {msg}", gamemaps.display())))?;
+    convert_map(&map).map_err(|err| match err {
+        Error::InvalidLevel(msg) => {
+            Error::InvalidLevel(format!("{}: {}: {msg}", gamemaps.display(), map.name))
+        }
+        err => err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG: u16 = 0xabcd;
+
+    fn words_to_bytes(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    //Builds a MAPHEAD and GAMEMAPS pair holding one map, the planes are stored
+    //RLEW compressed and wrapped in Carmack data without any pointers
+    fn build_files(width: u16, height: u16, planes: [&[u16]; 3]) -> (Vec<u8>, Vec<u8>) {
+        let mut gamemaps = b"TED5v1.0".to_vec();
+        let mut offsets = Vec::new();
+        let mut lengths = Vec::new();
+        for plane in planes {
+            let mut rlew = vec![(plane.len() * 2) as u16];
+            rlew.extend_from_slice(plane);
+            let mut carmack = vec![(rlew.len() * 2) as u16];
+            carmack.extend(rlew);
+            let data = words_to_bytes(&carmack);
+            offsets.push(gamemaps.len() as i32);
+            lengths.push(data.len() as u16);
+            gamemaps.extend(data);
+        }
+
+        let header_offset = gamemaps.len() as i32;
+        for offset in offsets {
+            gamemaps.extend(offset.to_le_bytes());
+        }
+        for length in lengths {
+            gamemaps.extend(length.to_le_bytes());
+        }
+        gamemaps.extend(width.to_le_bytes());
+        gamemaps.extend(height.to_le_bytes());
+        let mut name = [0u8; 16];
+        name[..4].copy_from_slice(b"Test");
+        gamemaps.extend(name);
+        assert_eq!(gamemaps.len() - header_offset as usize, MAP_HEADER_SIZE);
+
+        let mut maphead = TAG.to_le_bytes().to_vec();
+        maphead.extend(header_offset.to_le_bytes());
+        for _ in 1..MAX_MAPS {
+            maphead.extend(0i32.to_le_bytes());
+        }
+        (maphead, gamemaps)
+    }
+
+    #[test]
+    fn rlew_repeats_tagged_runs() {
+        let data = [12, 1, TAG, 4, 7, 2];
+        assert_eq!(rlew_expand(&data, TAG).unwrap(), vec![1, 7, 7, 7, 7, 2]);
+    }
+
+    #[test]
+    fn rlew_reports_truncated_data() {
+        assert!(rlew_expand(&[8, 1, TAG, 4], TAG).is_err());
+    }
+
+    #[test]
+    fn carmack_near_and_far_pointers() {
+        let mut data = words_to_bytes(&[16, 1, 2, 3]);
+        //Copy 2 words starting 3 words back: 1 2
+        data.extend([2, NEAR_POINTER, 3]);
+        //Copy 3 words starting at word 1: 2 3 1
+        data.extend([3, FAR_POINTER]);
+        data.extend(1u16.to_le_bytes());
+        assert_eq!(carmack_expand(&data).unwrap(), vec![1, 2, 3, 1, 2, 2, 3, 1]);
+    }
+
+    #[test]
+    fn carmack_overlapping_copy() {
+        let mut data = words_to_bytes(&[10, 5]);
+        data.extend([4, NEAR_POINTER, 1]);
+        assert_eq!(carmack_expand(&data).unwrap(), vec![5, 5, 5, 5, 5]);
+    }
+
+    #[test]
+    fn carmack_escaped_literal() {
+        let mut data = words_to_bytes(&[4]);
+        data.extend([0, NEAR_POINTER, 0x12, 0, FAR_POINTER, 0x34]);
+        assert_eq!(carmack_expand(&data).unwrap(), vec![0xa712, 0xa834]);
+    }
+
+    #[test]
+    fn carmack_rejects_pointer_before_start() {
+        let mut data = words_to_bytes(&[8, 1]);
+        data.extend([2, NEAR_POINTER, 5]);
+        assert!(carmack_expand(&data).is_err());
+    }
+
+    #[test]
+    fn converts_sample_map() {
+        #[rustfmt::skip]
+        let plane0 = [
+            1,   2,   3,   1,
+            1,   107, 90,  5,
+            1,   107, 107, 1,
+            1,   1,   1,   1,
+        ];
+        #[rustfmt::skip]
+        let plane1 = [
+            0, 0,  0,   0,
+            0, 20, 0,   0,
+            0, 48, 108, 0,
+            0, 0,  0,   0,
+        ];
+        let (maphead, gamemaps) = build_files(4, 4, [&plane0, &plane1, &[0; 16]]);
+
+        let map = read_map(&maphead, &gamemaps, 0).unwrap();
+        assert_eq!(map.name, "Test");
+        assert_eq!(map.plane0, plane0);
+
+        let level = convert_map(&map).unwrap();
+        assert_eq!(level.get_tile(0, 0), 1);
+        assert_eq!(level.get_tile(1, 0), 2);
+        assert_eq!(level.get_tile(3, 1), 1);
+        assert_eq!(level.get_tile(1, 1), 0);
+        assert_eq!(level.get_tile(2, 1), DOOR_TILE);
+        assert_eq!(level.doors.len(), 1);
+        assert_eq!((level.doors[0].x, level.doors[0].y), (2, 1));
+        assert_eq!(
+            (level.start.x, level.start.y, level.start.angle),
+            (1.5, 1.5, 0.0)
+        );
+
+        let kinds: Vec<&str> = level.sprites.iter().map(|s| s.kind.as_str()).collect();
+        assert_eq!(kinds, ["health", "enemy"]);
+        assert_eq!((level.sprites[1].x, level.sprites[1].y), (2.5, 2.5));
+    }
+
+    #[test]
+    fn missing_map_is_an_error() {
+        let (maphead, gamemaps) = build_files(1, 1, [&[0], &[0], &[0]]);
+        assert!(read_map(&maphead, &gamemaps, 1).is_err());
+        assert!(read_map(&maphead, &gamemaps, MAX_MAPS).is_err());
+    }
+
+    #[test]
+    fn wrong_plane_size_is_an_error() {
+        let (maphead, gamemaps) = build_files(2, 2, [&[1, 1, 1], &[0; 4], &[0; 4]]);
+        assert!(read_map(&maphead, &gamemaps, 0).is_err());
+    }
+}